@@ -59,6 +59,10 @@ pub const MAPPED_IO_START: u64 = 0x0900_0000;
 pub const LEGACY_SERIAL_MAPPED_IO_START: u64 = 0x0900_0000;
 pub const LEGACY_RTC_MAPPED_IO_START: u64 = 0x0901_0000;
 pub const LEGACY_GPIO_MAPPED_IO_START: u64 = 0x0902_0000;
+pub const LEGACY_TPM_MAPPED_IO_START: u64 = 0x0903_0000;
+
+/// TPM TIS MMIO window (5 localities, 4KiB each), matching the x86_64 size.
+pub const TPM_SIZE: u64 = 0x5000;
 
 /// Space 0x0905_0000 ~ 0x0906_0000 is reserved for pcie io address
 pub const MEM_PCI_IO_START: GuestAddress = GuestAddress(0x0905_0000);
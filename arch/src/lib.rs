@@ -118,6 +118,9 @@ pub enum DeviceType {
     /// Device Type: GPIO.
     #[cfg(target_arch = "aarch64")]
     Gpio,
+    /// Device Type: TPM.
+    #[cfg(target_arch = "aarch64")]
+    Tpm,
 }
 
 /// Default (smallest) memory page size for the supported architectures.
@@ -135,6 +138,7 @@ impl fmt::Display for DeviceType {
 pub struct MmioDeviceInfo {
     pub addr: u64,
     pub irq: u32,
+    pub length: u64,
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -146,6 +150,6 @@ impl DeviceInfoForFdt for MmioDeviceInfo {
         self.irq
     }
     fn length(&self) -> u64 {
-        4096
+        self.length
     }
 }
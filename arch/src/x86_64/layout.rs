@@ -98,6 +98,17 @@ pub const PCI_MMCONFIG_SIZE: u64 = 256 << 20;
 pub const IOAPIC_START: GuestAddress = GuestAddress(0xfec0_0000);
 pub const IOAPIC_SIZE: u64 = 0x20;
 
+// TPM TIS MMIO window (5 localities, 4KiB each)
+pub const TPM_START: GuestAddress = GuestAddress(0xfed4_0000);
+pub const TPM_SIZE: u64 = 0x5000;
+
+// TPM measured-boot event log: ordinary guest RAM, not MMIO, so it lives in
+// the low-memory EBDA range alongside the other boot-time tables (ACPI at
+// RSDP_POINTER, SMBIOS at SMBIOS_START) rather than in the 32-bit reserved
+// device hole above. Sized to end exactly at the start of high RAM.
+pub const TPM_LOG_START: GuestAddress = GuestAddress(0xf4000);
+pub const TPM_LOG_SIZE: u64 = 0xc000;
+
 // APIC
 pub const APIC_START: GuestAddress = GuestAddress(0xfee0_0000);
 
@@ -15,6 +15,20 @@ mod i8042;
 #[cfg(target_arch = "aarch64")]
 mod rtc_pl031;
 mod serial;
+#[cfg(feature = "tpm")]
+mod tpm;
+#[cfg(feature = "tpm")]
+mod tpm_audit;
+#[cfg(feature = "tpm")]
+mod tpm_mmio_trace;
+#[cfg(feature = "tpm")]
+mod tpm_pcap_trace;
+#[cfg(feature = "tpm")]
+mod tpm_pci;
+#[cfg(feature = "tpm")]
+mod tpm_tis;
+#[cfg(feature = "tpm")]
+mod tpm_tis_core;
 #[cfg(target_arch = "aarch64")]
 mod uart_pl011;
 
@@ -24,6 +38,23 @@ pub use self::cmos::Cmos;
 pub use self::fwdebug::FwDebugDevice;
 pub use self::i8042::I8042Device;
 pub use self::serial::Serial;
+#[cfg(feature = "tpm")]
+pub use self::tpm::{new as new_tpm_backend, TPMBackendConfig};
+#[cfg(feature = "tpm")]
+pub use self::tpm_audit::TpmAuditLog;
+#[cfg(feature = "tpm")]
+pub use self::tpm_mmio_trace::{replay as replay_tpm_mmio_trace, MmioDivergence, TpmMmioTrace};
+#[cfg(feature = "tpm")]
+pub use self::tpm_pcap_trace::{TpmPcapReplay, TpmPcapTrace};
+#[cfg(feature = "tpm")]
+pub use self::tpm_pci::{Error as TpmPciError, TpmPciDevice};
+#[cfg(feature = "tpm")]
+pub use self::tpm_tis::{
+    Error as TpmTisError, TpmBufferSizeLimits, TpmDeviceIdentity, TpmDeviceInfo, TpmLocalityInfo,
+    TPMIsa, DEFAULT_MAX_GUEST_LOCALITY as TPM_DEFAULT_MAX_GUEST_LOCALITY, TPM_TIS_BUFFER_MAX,
+};
+#[cfg(feature = "tpm")]
+pub use self::tpm_tis_core::TpmIommuTranslate;
 
 #[cfg(target_arch = "aarch64")]
 pub use self::gpio_pl061::Error as GpioDeviceError;
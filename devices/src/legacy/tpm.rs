@@ -0,0 +1,182 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Construction of the [`vtpm::TpmBackend`] used by [`super::tpm_tis::TPMIsa`].
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use vtpm::{DenyListBackend, ReconnectPolicy, TpmBackend, TpmEmulator, TpmSimulator};
+
+/// Where the actual TPM implementation lives.
+pub enum TPMBackendConfig {
+    /// An external `swtpm` process, reachable over its control and data
+    /// channel Unix sockets.
+    Emulator {
+        ctrl_path: PathBuf,
+        data_path: PathBuf,
+        /// Don't dial `swtpm` yet; connect lazily on first backend access
+        /// (or an explicit `vm.tpm-reconnect`) instead, so constructing this
+        /// device doesn't fail just because swtpm hasn't started listening
+        /// yet.
+        defer_connect: bool,
+        /// Retry count/backoff for the initial dial (and any later
+        /// reconnect), see [`ReconnectPolicy`].
+        reconnect_policy: ReconnectPolicy,
+    },
+    /// An external `swtpm` process exposing its control and data channels
+    /// over TCP instead, e.g. running in a different network namespace or
+    /// on a separate host. `ctrl_port` is the control channel; the data
+    /// channel is expected one port above it.
+    EmulatorTcp {
+        host: String,
+        ctrl_port: u16,
+        /// See the `Emulator` variant's `defer_connect` field above.
+        defer_connect: bool,
+        /// See the `Emulator` variant's `reconnect_policy` field above.
+        reconnect_policy: ReconnectPolicy,
+    },
+    /// An in-process TPM 2.0 simulator (the `tpm2` crate), for deployments
+    /// that would rather not manage a separate `swtpm` process.
+    Builtin {
+        state_dir: PathBuf,
+        /// Owning uid/gid to apply to `state_dir` once the simulator has
+        /// finished laying out its files, e.g. so a host that drops
+        /// privileges after setup can still reach its own TPM state.
+        /// `None` leaves that part of the ownership unchanged.
+        state_dir_uid: Option<u32>,
+        state_dir_gid: Option<u32>,
+        /// Permission bits (e.g. `0o700`) to apply to `state_dir`. `None`
+        /// leaves the mode the simulator created it with unchanged.
+        state_dir_mode: Option<u32>,
+    },
+    /// A previously captured [`super::tpm_pcap_trace::TpmPcapTrace`], replayed
+    /// as a stand-in backend for reproducing a guest-driver bug offline; see
+    /// [`super::tpm_pcap_trace::TpmPcapReplay`].
+    Replay { trace_path: PathBuf },
+}
+
+/// Instantiate the backend described by `config`.
+///
+/// Returned wrapped in `Arc<Mutex<..>>` rather than handed back bare: the
+/// [`super::tpm_tis::TPMIsa`] frontend's vCPU-facing register accesses and a
+/// backend's own blocking socket I/O can run on different threads, and
+/// `TpmBackend`'s `&mut self` methods need that external synchronization to
+/// be shared safely rather than relying on unsafe interior mutability.
+///
+/// `deny_commands` lists TPM2 command ordinals the backend should refuse to
+/// execute (e.g. for a hardened multi-tenant host); pass an empty slice to
+/// leave the backend unfiltered.
+///
+/// `ek_cert` provisions an endorsement key certificate into the backend's NV
+/// storage at the standard RSA EK cert index, so guest attestation flows
+/// find a valid cert without the guest having to provision one itself; pass
+/// `None` to leave NV storage untouched. Provisioning talks to the backend
+/// immediately, so combining `ek_cert` with a `defer_connect: true` backend
+/// config that isn't reachable yet fails this call; reconnect the backend
+/// first (or don't defer the connection) when both are needed together.
+///
+/// For `TPMBackendConfig::Builtin`, `state_dir_uid`/`state_dir_gid`/
+/// `state_dir_mode` are applied to `state_dir` right after the simulator has
+/// created its files there, so an unprivileged owner can still reach them.
+/// The `Emulator`/`EmulatorTcp` variants have no equivalent: this process
+/// only connects to an already-running `swtpm`'s sockets, it never creates
+/// them, so there is no local path for this function to apply ownership or
+/// permissions to.
+///
+/// A backend that dials successfully but then fails its `TPM2_Startup`
+/// handshake doesn't fail this call: a guest can still boot without a
+/// working TPM (the firmware/OS just skips measured boot), so taking down
+/// the whole VM over a backend that merely failed to initialize would be
+/// disproportionate. The returned `bool` is `true` if startup succeeded;
+/// when it's `false`, the caller is expected to construct the device
+/// anyway and let it report every register access as absent hardware (see
+/// [`super::tpm_tis::TPMIsa::new`]'s `startup_failed` parameter) rather
+/// than quietly pretending the backend works. A failure to even construct
+/// the backend in the first place (bad config, unreachable socket with
+/// `defer-connect=off`, ...) is still a hard error: there is no backend
+/// object to hand back degraded.
+pub fn new(
+    config: TPMBackendConfig,
+    deny_commands: &[u32],
+    ek_cert: Option<&[u8]>,
+) -> vtpm::Result<(Arc<Mutex<dyn TpmBackend>>, bool)> {
+    let mut pending_clear_marker = None;
+    let mut startup_ok = true;
+    let mut backend: Box<dyn TpmBackend> = match config {
+        TPMBackendConfig::Emulator {
+            ctrl_path,
+            data_path,
+            defer_connect,
+            reconnect_policy,
+        } => {
+            let mut emulator = if defer_connect {
+                TpmEmulator::new_deferred(ctrl_path, data_path, reconnect_policy)
+            } else {
+                TpmEmulator::new(ctrl_path, data_path, reconnect_policy)?
+            };
+            if let Err(e) = emulator.startup(vtpm::ptm::PtmInit::default()) {
+                warn!("TPM backend failed to start up ({e}); continuing with a degraded TPM device");
+                startup_ok = false;
+            }
+            Box::new(emulator)
+        }
+        TPMBackendConfig::EmulatorTcp {
+            host,
+            ctrl_port,
+            defer_connect,
+            reconnect_policy,
+        } => {
+            let mut emulator = if defer_connect {
+                TpmEmulator::new_tcp_deferred(host, ctrl_port, reconnect_policy)
+            } else {
+                TpmEmulator::new_tcp(host, ctrl_port, reconnect_policy)?
+            };
+            if let Err(e) = emulator.startup(vtpm::ptm::PtmInit::default()) {
+                warn!("TPM backend failed to start up ({e}); continuing with a degraded TPM device");
+                startup_ok = false;
+            }
+            Box::new(emulator)
+        }
+        TPMBackendConfig::Builtin {
+            state_dir,
+            state_dir_uid,
+            state_dir_gid,
+            state_dir_mode,
+        } => {
+            let mut simulator = TpmSimulator::new(&state_dir)?;
+            vtpm::secure_state_dir(&state_dir, state_dir_uid, state_dir_gid, state_dir_mode)?;
+            if let Err(e) = simulator.startup(vtpm::ptm::PtmInit::default()) {
+                warn!("TPM backend failed to start up ({e}); continuing with a degraded TPM device");
+                startup_ok = false;
+            } else {
+                let marker = vtpm::clear::marker_path(&state_dir);
+                vtpm::clear::apply_pending_clear(&mut simulator, &marker)?;
+                pending_clear_marker = Some(marker);
+            }
+            Box::new(simulator)
+        }
+        TPMBackendConfig::Replay { trace_path } => {
+            Box::new(super::tpm_pcap_trace::TpmPcapReplay::new(&trace_path)?)
+        }
+    };
+
+    if startup_ok {
+        if let Some(cert) = ek_cert {
+            vtpm::ek_cert::provision_ek_cert(
+                backend.as_mut(),
+                vtpm::ek_cert::RSA_EK_CERT_NV_INDEX,
+                cert,
+            )?;
+        }
+    }
+
+    let backend = if deny_commands.is_empty() {
+        Arc::new(Mutex::new(backend)) as Arc<Mutex<dyn TpmBackend>>
+    } else {
+        let policy = DenyListBackend::new(backend, deny_commands.iter().copied(), pending_clear_marker);
+        Arc::new(Mutex::new(policy)) as Arc<Mutex<dyn TpmBackend>>
+    };
+    Ok((backend, startup_ok))
+}
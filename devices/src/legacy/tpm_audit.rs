@@ -0,0 +1,92 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structured (JSON Lines) audit trail of TPM commands dispatched through
+//! [`TPMIsa`](super::tpm_tis::TPMIsa), for compliance deployments that need
+//! a durable, machine-parsable record independent of the general purpose
+//! `event_monitor` (whose single shared, pretty-printed file is meant for a
+//! human or a debugging tool, not per-VM log collection).
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::Serialize;
+
+/// One audit record per command dispatched to the backend.
+#[derive(Serialize)]
+struct TpmAuditRecord<'a> {
+    /// Seconds since the Unix epoch; `0` if the host clock is somehow before
+    /// it, rather than failing the record over a cosmetic field.
+    timestamp: u64,
+    id: &'a str,
+    locality: u8,
+    /// TPM2 command code (e.g. `0x17b` for `TPM2_GetRandom`), read directly
+    /// out of the command header. `None` for a command shorter than a
+    /// well-formed header, which the backend would have rejected anyway.
+    ordinal: Option<u32>,
+    command_size: usize,
+    response_size: usize,
+    /// TPM2 response code, `None` on the same "too short to have a header"
+    /// basis as `ordinal`.
+    response_code: Option<u32>,
+}
+
+/// A TPM2 command/response header is a 2 byte tag, a 4 byte size, then a
+/// 4 byte command (request) or response (response) code; both share this
+/// offset, so one helper reads either.
+fn header_code(buf: &[u8]) -> Option<u32> {
+    buf.get(6..10).map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+}
+
+/// Appends one JSON Lines record per dispatched command to a host file.
+pub struct TpmAuditLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl TpmAuditLog {
+    pub fn new(path: PathBuf) -> io::Result<Self> {
+        let file = Self::open(&path)?;
+        Ok(TpmAuditLog { path, file })
+    }
+
+    fn open(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    /// Re-opens the log file at the same path, so a file an external tool
+    /// (e.g. `logrotate`) has renamed or removed out from under this handle
+    /// gets replaced with a fresh one at the original path rather than this
+    /// log silently continuing to append to the renamed file forever.
+    /// Nothing in this tree calls this on a timer or signal yet; it exists
+    /// as the hook a future `SIGHUP` handler or API endpoint can call.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file = Self::open(&self.path)?;
+        Ok(())
+    }
+
+    /// Appends a record for one command/response round trip. Best effort:
+    /// a write failure here must not take the TPM device down with it, so
+    /// errors are dropped rather than propagated.
+    pub fn record(&mut self, id: &str, locality: u8, command: &[u8], response: &[u8]) {
+        let record = TpmAuditRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            id,
+            locality,
+            ordinal: header_code(command),
+            command_size: command.len(),
+            response_size: response.len(),
+            response_code: header_code(response),
+        };
+        if let Ok(mut line) = serde_json::to_vec(&record) {
+            line.push(b'\n');
+            let _ = self.file.write_all(&line);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! pcap capture of commands dispatched through
+//! [`TPMIsa`](super::tpm_tis::TPMIsa), for offline inspection with
+//! `tcpdump`/Wireshark when a guest's attestation flow misbehaves and
+//! reproducing it live isn't practical. Complements
+//! [`super::tpm_audit::TpmAuditLog`]'s JSON Lines trail, which is built for
+//! durable compliance records rather than for loading into a packet
+//! analyzer.
+//!
+//! This writes a plain classic pcap file (not pcap-ng, which would need a
+//! block writer this crate has no other use for), using linktype 147
+//! (`LINKTYPE_USER0`, reserved by the pcap linktype registry for exactly
+//! this kind of private framing between a cooperating writer and reader).
+//! pcap itself carries no notion of packet direction, so each captured
+//! payload is prefixed with one direction byte
+//! ([`DIRECTION_COMMAND`]/[`DIRECTION_RESPONSE`]) ahead of the raw TPM
+//! command or response bytes. tpm2-tools/Wireshark's own TPM dissector
+//! expects TCP or a registered TPM-specific linktype rather than
+//! `LINKTYPE_USER0`, so reading a capture from this module back with either
+//! today means a generic hex/raw view, or a small Lua dissector taught this
+//! module's framing, rather than out of the box TPM-aware decoding.
+//!
+//! [`TpmPcapReplay`] reads a capture back the other way, as a
+//! [`vtpm::TpmBackend`] that replays its recorded responses instead of
+//! talking to a real backend: paired with
+//! [`super::tpm_mmio_trace::replay`] driving the same session's recorded
+//! guest-visible MMIO accesses into a fresh [`super::tpm_tis_core::TpmTisCore`],
+//! this reproduces a whole guest-driver session offline, without swtpm or
+//! the original guest.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use vtpm::ptm::{Capabilities, PtmGetConfig, PtmInit, PtmSetBufferSize, StateBlobType};
+use vtpm::{Error, Result, TpmBackend};
+
+const LINKTYPE_USER0: u32 = 147;
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+/// Matches `vtpm::emulator::TpmEmulator`'s own cap on a single response's
+/// claimed size, so a capture can never record more than a backend could
+/// actually have produced in one command/response exchange.
+const SNAPLEN: u32 = 1 << 20;
+
+const DIRECTION_COMMAND: u8 = 0;
+const DIRECTION_RESPONSE: u8 = 1;
+
+fn write_global_header(file: &mut File) -> io::Result<()> {
+    file.write_all(&PCAP_MAGIC.to_le_bytes())?;
+    file.write_all(&PCAP_VERSION_MAJOR.to_le_bytes())?;
+    file.write_all(&PCAP_VERSION_MINOR.to_le_bytes())?;
+    file.write_all(&0i32.to_le_bytes())?; // thiszone: capture is always in UTC
+    file.write_all(&0u32.to_le_bytes())?; // sigfigs: unused by every reader that matters
+    file.write_all(&SNAPLEN.to_le_bytes())?;
+    file.write_all(&LINKTYPE_USER0.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_record(file: &mut File, direction: u8, payload: &[u8]) -> io::Result<()> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let len = (payload.len() + 1) as u32;
+    file.write_all(&(since_epoch.as_secs() as u32).to_le_bytes())?;
+    file.write_all(&since_epoch.subsec_micros().to_le_bytes())?;
+    file.write_all(&len.to_le_bytes())?; // incl_len
+    file.write_all(&len.to_le_bytes())?; // orig_len: never truncated here
+    file.write_all(&[direction])?;
+    file.write_all(payload)
+}
+
+/// Appends one pcap record per direction of a dispatched command/response
+/// round trip to a host file.
+pub struct TpmPcapTrace {
+    file: File,
+}
+
+impl TpmPcapTrace {
+    /// Creates (truncating if it already exists) a pcap capture at `path`.
+    pub fn new(path: PathBuf) -> io::Result<Self> {
+        let mut file = File::create(&path)?;
+        write_global_header(&mut file)?;
+        Ok(TpmPcapTrace { file })
+    }
+
+    /// Appends a command record then a response record for one round trip.
+    /// Best effort: a write failure here must not take the TPM device down
+    /// with it, so errors are dropped rather than propagated.
+    pub fn record(&mut self, command: &[u8], response: &[u8]) {
+        let _ = write_record(&mut self.file, DIRECTION_COMMAND, command);
+        let _ = write_record(&mut self.file, DIRECTION_RESPONSE, response);
+    }
+}
+
+/// Reads the pcap record starting at `offset` and returns
+/// `(next_offset, direction, payload)`, or `None` once `bytes` is exhausted (a partial
+/// trailing record, from a capture that was still being written when it was
+/// copied out, is treated the same as a clean end rather than an error).
+fn read_record(bytes: &[u8], offset: usize) -> Option<(usize, u8, Vec<u8>)> {
+    let header = bytes.get(offset..offset + 16)?;
+    let incl_len = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+    let payload = bytes.get(offset + 16..offset + 16 + incl_len)?;
+    let (&direction, payload) = payload.split_first()?;
+    Some((offset + 16 + incl_len, direction, payload.to_vec()))
+}
+
+/// Replays a previously captured [`TpmPcapTrace`] as a [`TpmBackend`], for
+/// reproducing a guest-driver bug offline without a live `swtpm`: each
+/// [`TpmBackend::deliver_request`] call returns the next recorded response
+/// in the order [`TpmPcapTrace::record`] originally wrote it. A replayed
+/// command that no longer matches the one recorded at this point is only
+/// logged, not rejected, since a driver bug under investigation is often
+/// exactly why the two have started to differ.
+pub struct TpmPcapReplay {
+    pairs: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl TpmPcapReplay {
+    /// Parses `path` back into an ordered queue of `(command, response)`
+    /// pairs. A trace containing an unpaired trailing command (the capture
+    /// stopped mid round trip) drops that last command rather than
+    /// surfacing a synthetic empty response for it.
+    pub fn new(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).map_err(|e| Error::Simulator(e.to_string()))?;
+        let mut pairs = VecDeque::new();
+        let mut pending_command = None;
+        let mut offset = 24; // past the global header
+        while let Some((next_offset, direction, payload)) = read_record(&bytes, offset) {
+            match direction {
+                DIRECTION_COMMAND => pending_command = Some(payload),
+                DIRECTION_RESPONSE => {
+                    if let Some(command) = pending_command.take() {
+                        pairs.push_back((command, payload));
+                    }
+                }
+                _ => {}
+            }
+            offset = next_offset;
+        }
+        Ok(TpmPcapReplay { pairs })
+    }
+}
+
+impl TpmBackend for TpmPcapReplay {
+    fn startup(&mut self, _init: PtmInit) -> Result<()> {
+        Ok(())
+    }
+
+    fn store_volatile(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+        let (recorded_cmd, response) = self
+            .pairs
+            .pop_front()
+            .ok_or(Error::NotRunning("replay trace exhausted"))?;
+        if recorded_cmd != cmd {
+            warn!(
+                "TPM replay: command diverged from the recorded trace ({} recorded bytes vs {} replayed)",
+                recorded_cmd.len(),
+                cmd.len()
+            );
+        }
+        Ok(response)
+    }
+
+    fn cancel_cmd(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_established_flag(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_locality(&mut self, _locality: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize> {
+        Ok(PtmSetBufferSize {
+            buffersize: requested,
+            minsize: requested,
+            maxsize: requested,
+        })
+    }
+
+    fn hash_start(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn hash_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_config(&mut self) -> Result<PtmGetConfig> {
+        Ok(PtmGetConfig { flags: 0 })
+    }
+
+    fn get_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _passphrase: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn set_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _data: &[u8],
+        _passphrase: Option<&[u8]>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        Ok(Capabilities::all())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "ch-tpm-pcap-trace-test-{}-{}.pcap",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn test_new_writes_a_valid_pcap_global_header() {
+        let path = trace_path("global-header");
+        let _trace = TpmPcapTrace::new(path.clone()).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 24);
+        assert_eq!(&bytes[0..4], &PCAP_MAGIC.to_le_bytes());
+        assert_eq!(
+            u32::from_le_bytes(bytes[20..24].try_into().unwrap()),
+            LINKTYPE_USER0
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_record_appends_a_command_then_response_pair() {
+        let path = trace_path("command-response");
+        let mut trace = TpmPcapTrace::new(path.clone()).unwrap();
+        trace.record(&[1, 2, 3], &[4, 5]);
+
+        let bytes = std::fs::read(&path).unwrap();
+        let command_record = &bytes[24..];
+        assert_eq!(command_record[16], DIRECTION_COMMAND);
+        assert_eq!(&command_record[17..20], &[1, 2, 3]);
+
+        let response_record = &bytes[24 + 16 + 4..];
+        assert_eq!(response_record[16], DIRECTION_RESPONSE);
+        assert_eq!(&response_record[17..19], &[4, 5]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
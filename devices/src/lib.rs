@@ -10,8 +10,14 @@
 #[macro_use]
 extern crate bitflags;
 #[macro_use]
+extern crate event_monitor;
+#[macro_use]
 extern crate log;
 
+use vm_memory::bitmap::AtomicBitmap;
+
+type GuestMemoryMmap = vm_memory::GuestMemoryMmap<AtomicBitmap>;
+
 #[cfg(feature = "acpi")]
 pub mod acpi;
 #[cfg(target_arch = "aarch64")]
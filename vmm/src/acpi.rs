@@ -371,6 +371,25 @@ fn create_spcr_table(base_address: u64, gsi: u32) -> Sdt {
     spcr
 }
 
+#[cfg(target_arch = "x86_64")]
+fn create_tpm2_table(log_area_start: u64, log_area_size: u32) -> Sdt {
+    // TPM2, per the TCG ACPI Specification for TPM 2.0 devices. Describes
+    // the TIS MMIO interface to guest firmware/OS and, via the Log Area
+    // fields, the measured-boot event log buffer reserved in guest memory
+    // so firmware can append TCG_PCR_EVENT2 records to it.
+    let mut tpm2 = Sdt::new(*b"TPM2", 76, 4, *b"CLOUDH", *b"CHTPM2  ", 1);
+    // Start Method: 6 = Memory Mapped I/O, matching the TIS interface.
+    tpm2.write(48, 6u32);
+    // Log Area Minimum Length
+    tpm2.write(64, log_area_size);
+    // Log Area Start Address
+    tpm2.write(68, log_area_start);
+
+    tpm2.update_checksum();
+
+    tpm2
+}
+
 #[cfg(target_arch = "aarch64")]
 fn create_iort_table() -> Sdt {
     const ACPI_IORT_NODE_ITS_GROUP: u8 = 0x00;
@@ -509,6 +528,21 @@ pub fn create_acpi_tables(
     prev_tbl_len = mcfg.len() as u64;
     prev_tbl_off = mcfg_offset;
 
+    // TPM2
+    // Only created when a TPM device is present, since the table just
+    // describes how to find one.
+    #[cfg(target_arch = "x86_64")]
+    if let Some((log_start, log_size)) = device_manager.lock().unwrap().tpm_event_log_region() {
+        let tpm2 = create_tpm2_table(log_start.0, log_size as u32);
+        let tpm2_offset = prev_tbl_off.checked_add(prev_tbl_len).unwrap();
+        guest_mem
+            .write_slice(tpm2.as_slice(), tpm2_offset)
+            .expect("Error writing TPM2 table");
+        tables.push(tpm2_offset.0);
+        prev_tbl_len = tpm2.len() as u64;
+        prev_tbl_off = tpm2_offset;
+    }
+
     // SPCR
     #[cfg(target_arch = "aarch64")]
     {
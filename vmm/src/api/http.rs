@@ -103,6 +103,12 @@ pub enum HttpError {
     /// Could not get counters from VM
     VmCounters(ApiError),
 
+    /// Could not get TPM info from VM
+    VmTpmInfo(ApiError),
+
+    /// Could not get the TPM event log from VM
+    VmTpmEventLog(ApiError),
+
     /// Error setting up migration received
     VmReceiveMigration(ApiError),
 
@@ -111,6 +117,21 @@ pub enum HttpError {
 
     /// Error activating power button
     VmPowerButton(ApiError),
+
+    /// Could not reconnect the TPM backend
+    VmTpmReconnect(ApiError),
+
+    /// Could not get the TPM establishment flag from VM
+    VmTpmEstablishment(ApiError),
+
+    /// Could not reset the TPM establishment flag
+    VmTpmResetEstablishedFlag(ApiError),
+
+    /// Could not mark the TPM device's OS handoff point reached
+    VmTpmOsHandoff(ApiError),
+
+    /// Could not wipe and reinitialize the TPM backend's permanent state
+    VmTpmResetState(ApiError),
 }
 
 impl From<serde_json::Error> for HttpError {
@@ -226,6 +247,12 @@ lazy_static! {
         r.routes.insert(endpoint!("/vm.send-migration"), Box::new(VmActionHandler::new(VmAction::SendMigration(Arc::default()))));
         r.routes.insert(endpoint!("/vm.shutdown"), Box::new(VmActionHandler::new(VmAction::Shutdown)));
         r.routes.insert(endpoint!("/vm.snapshot"), Box::new(VmActionHandler::new(VmAction::Snapshot(Arc::default()))));
+        r.routes.insert(endpoint!("/vm.tpm-establishment"), Box::new(VmActionHandler::new(VmAction::TpmEstablishment(None))));
+        r.routes.insert(endpoint!("/vm.tpm-event-log"), Box::new(VmActionHandler::new(VmAction::TpmEventLog)));
+        r.routes.insert(endpoint!("/vm.tpm-info"), Box::new(VmActionHandler::new(VmAction::TpmInfo(None))));
+        r.routes.insert(endpoint!("/vm.tpm-os-handoff"), Box::new(VmActionHandler::new(VmAction::TpmOsHandoff(None))));
+        r.routes.insert(endpoint!("/vm.tpm-reconnect"), Box::new(VmActionHandler::new(VmAction::TpmReconnect(None))));
+        r.routes.insert(endpoint!("/vm.tpm-reset-state"), Box::new(VmActionHandler::new(VmAction::TpmResetState(None))));
         r.routes.insert(endpoint!("/vmm.ping"), Box::new(VmmPing {}));
         r.routes.insert(endpoint!("/vmm.shutdown"), Box::new(VmmShutdown {}));
 
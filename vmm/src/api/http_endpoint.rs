@@ -8,8 +8,9 @@ use crate::api::{
     vm_add_device, vm_add_disk, vm_add_fs, vm_add_net, vm_add_pmem, vm_add_vsock, vm_boot,
     vm_counters, vm_create, vm_delete, vm_info, vm_pause, vm_power_button, vm_reboot,
     vm_receive_migration, vm_remove_device, vm_resize, vm_resize_zone, vm_restore, vm_resume,
-    vm_send_migration, vm_shutdown, vm_snapshot, vmm_ping, vmm_shutdown, ApiRequest, VmAction,
-    VmConfig,
+    vm_send_migration, vm_shutdown, vm_snapshot, vm_tpm_establishment, vm_tpm_event_log,
+    vm_tpm_info, vm_tpm_os_handoff, vm_tpm_reconnect, vm_tpm_reset_established_flag,
+    vm_tpm_reset_state, vmm_ping, vmm_shutdown, ApiRequest, VmAction, VmConfig, VmTpmInfoData,
 };
 use micro_http::{Body, Method, Request, Response, StatusCode, Version};
 use std::sync::mpsc::Sender;
@@ -168,6 +169,30 @@ impl EndpointHandler for VmActionHandler {
                 )
                 .map_err(HttpError::VmSendMigration),
 
+                TpmReconnect(_) => {
+                    let data: VmTpmInfoData = serde_json::from_slice(body.raw())?;
+                    vm_tpm_reconnect(api_notifier, api_sender, data.id)
+                        .map_err(HttpError::VmTpmReconnect)
+                }
+
+                TpmEstablishment(_) => {
+                    let data: VmTpmInfoData = serde_json::from_slice(body.raw())?;
+                    vm_tpm_reset_established_flag(api_notifier, api_sender, data.id)
+                        .map_err(HttpError::VmTpmResetEstablishedFlag)
+                }
+
+                TpmOsHandoff(_) => {
+                    let data: VmTpmInfoData = serde_json::from_slice(body.raw())?;
+                    vm_tpm_os_handoff(api_notifier, api_sender, data.id)
+                        .map_err(HttpError::VmTpmOsHandoff)
+                }
+
+                TpmResetState(_) => {
+                    let data: VmTpmInfoData = serde_json::from_slice(body.raw())?;
+                    vm_tpm_reset_state(api_notifier, api_sender, data.id)
+                        .map_err(HttpError::VmTpmResetState)
+                }
+
                 _ => Err(HttpError::BadRequest),
             }
         } else {
@@ -181,6 +206,16 @@ impl EndpointHandler for VmActionHandler {
                 PowerButton => {
                     vm_power_button(api_notifier, api_sender).map_err(HttpError::VmPowerButton)
                 }
+                TpmReconnect(id) => vm_tpm_reconnect(api_notifier, api_sender, id)
+                    .map_err(HttpError::VmTpmReconnect),
+                TpmEstablishment(id) => {
+                    vm_tpm_reset_established_flag(api_notifier, api_sender, id)
+                        .map_err(HttpError::VmTpmResetEstablishedFlag)
+                }
+                TpmOsHandoff(id) => vm_tpm_os_handoff(api_notifier, api_sender, id)
+                    .map_err(HttpError::VmTpmOsHandoff),
+                TpmResetState(id) => vm_tpm_reset_state(api_notifier, api_sender, id)
+                    .map_err(HttpError::VmTpmResetState),
                 _ => Err(HttpError::BadRequest),
             }
         }
@@ -190,11 +225,35 @@ impl EndpointHandler for VmActionHandler {
         &self,
         api_notifier: EventFd,
         api_sender: Sender<ApiRequest>,
-        _body: &Option<Body>,
+        body: &Option<Body>,
     ) -> std::result::Result<Option<Body>, HttpError> {
         use VmAction::*;
         match self.action {
             Counters => vm_counters(api_notifier, api_sender).map_err(HttpError::VmCounters),
+            TpmInfo(_) => {
+                let id = match body {
+                    Some(body) => {
+                        let data: VmTpmInfoData = serde_json::from_slice(body.raw())?;
+                        data.id
+                    }
+                    None => None,
+                };
+                vm_tpm_info(api_notifier, api_sender, id).map_err(HttpError::VmTpmInfo)
+            }
+            TpmEventLog => {
+                vm_tpm_event_log(api_notifier, api_sender).map_err(HttpError::VmTpmEventLog)
+            }
+            TpmEstablishment(_) => {
+                let id = match body {
+                    Some(body) => {
+                        let data: VmTpmInfoData = serde_json::from_slice(body.raw())?;
+                        data.id
+                    }
+                    None => None,
+                };
+                vm_tpm_establishment(api_notifier, api_sender, id)
+                    .map_err(HttpError::VmTpmEstablishment)
+            }
             _ => Err(HttpError::BadRequest),
         }
     }
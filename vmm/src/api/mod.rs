@@ -141,6 +141,19 @@ pub enum ApiError {
 
     /// Error triggering power button
     VmPowerButton(VmError),
+
+    /// The TPM backend could not be reconnected.
+    VmTpmReconnect(VmError),
+
+    /// The TPM establishment flag could not be reset.
+    VmTpmResetEstablishedFlag(VmError),
+
+    /// The TPM device's OS handoff point could not be marked reached.
+    VmTpmOsHandoff(VmError),
+
+    /// The TPM backend's permanent state could not be wiped and
+    /// reinitialized.
+    VmTpmResetState(VmError),
 }
 pub type ApiResult<T> = std::result::Result<T, ApiError>;
 
@@ -150,6 +163,10 @@ pub struct VmInfo {
     pub state: VmState,
     pub memory_actual_size: u64,
     pub device_tree: Option<Arc<Mutex<DeviceTree>>>,
+    /// The first configured TPM device's register state and backend status,
+    /// for diagnosing a stuck TPM driver without a separate `vm.tpm-info`
+    /// call. `None` when no TPM is configured (or the `tpm` feature is off).
+    pub tpm: Option<VmTpmInfo>,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -157,6 +174,67 @@ pub struct VmmPingResponse {
     pub version: String,
 }
 
+/// Per-locality TIS register snapshot, part of [`VmTpmInfo`].
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct VmTpmLocalityInfo {
+    pub access: u8,
+    pub sts: u8,
+    pub inte: u32,
+    pub ints: u32,
+    pub int_vector: u8,
+}
+
+/// A PCR bank the backend reported supporting, part of [`VmTpmInfo`].
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct VmPcrBankInfo {
+    pub algorithm_id: u16,
+    pub algorithm_name: String,
+    pub pcr_select: Vec<u8>,
+}
+
+/// Response for `vm.tpm-info`: a debugging snapshot of the TPM device's
+/// state, for diagnosing guest TPM driver issues without a debugger.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct VmTpmInfo {
+    pub backend: String,
+    pub backend_healthy: bool,
+    pub state_encrypted: bool,
+    /// Negotiated guest-to-device command buffer size.
+    pub cmd_buffer_size: u32,
+    /// Negotiated device-to-guest response buffer size.
+    pub resp_buffer_size: u32,
+    pub active_locality: Option<u8>,
+    pub established_flag: bool,
+    pub localities: Vec<VmTpmLocalityInfo>,
+    pub pcr_banks: Vec<VmPcrBankInfo>,
+    pub self_test_done: bool,
+    /// Backend version reported via `CmdGetInfo` at construction time, for
+    /// bug reports. `0` if the backend doesn't support the command (e.g.
+    /// the built-in simulator) or wasn't reachable yet.
+    pub backend_version: u32,
+    /// Free-form build info string reported alongside `backend_version`.
+    /// Empty under the same conditions as `backend_version`.
+    pub backend_build_info: String,
+}
+
+/// Response for `vm.tpm-establishment`: whether the TPM establishment flag
+/// is currently set. Mirrors `TPM_TIS_ACCESS_TPM_ESTABLISHMENT`: only
+/// localities 3 and 4 are trusted to reset it (TCG PC Client Platform TPM
+/// Profile, 5.2), which is why resetting it goes through this dedicated
+/// host-initiated API rather than a guest-facing locality.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct VmTpmEstablishmentInfo {
+    pub established: bool,
+}
+
+/// Optional request body for `vm.tpm-info`, selecting which TPM device to
+/// query when more than one is configured. Omitting the body, or `id`,
+/// defaults to the first configured TPM device.
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmTpmInfoData {
+    pub id: Option<String>,
+}
+
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
 pub struct VmResizeData {
     pub desired_vcpus: Option<u8>,
@@ -245,6 +323,33 @@ pub enum ApiRequest {
     /// Get counters for a VM.
     VmCounters(Sender<ApiResponse>),
 
+    /// Get a TPM device's debug/introspection info for a VM. `None` selects
+    /// the first configured TPM device.
+    VmTpmInfo(Option<String>, Sender<ApiResponse>),
+
+    /// Dump the TPM measured-boot event log for a VM.
+    VmTpmEventLog(Sender<ApiResponse>),
+
+    /// Dial a TPM backend that hasn't connected yet (see `defer-connect`).
+    /// `None` selects the first configured TPM device.
+    VmTpmReconnect(Option<String>, Sender<ApiResponse>),
+
+    /// Query a TPM device's establishment flag. `None` selects the first
+    /// configured TPM device.
+    VmTpmEstablishment(Option<String>, Sender<ApiResponse>),
+
+    /// Reset a TPM device's establishment flag. `None` selects the first
+    /// configured TPM device.
+    VmTpmResetEstablishedFlag(Option<String>, Sender<ApiResponse>),
+
+    /// Mark a TPM device's OS handoff point reached. `None` selects the
+    /// first configured TPM device.
+    VmTpmOsHandoff(Option<String>, Sender<ApiResponse>),
+
+    /// Wipe a TPM backend's permanent state and reinitialize it. `None`
+    /// selects the first configured TPM device.
+    VmTpmResetState(Option<String>, Sender<ApiResponse>),
+
     /// Shut the previously booted virtual machine down.
     /// If the VM was not previously booted or created, the VMM API server
     /// will send a VmShutdown error back.
@@ -346,6 +451,33 @@ pub enum VmAction {
     /// Return VM counters
     Counters,
 
+    /// Return a TPM device's debug/introspection info. `None` selects the
+    /// first configured TPM device.
+    TpmInfo(Option<String>),
+
+    /// Dump the TPM measured-boot event log
+    TpmEventLog,
+
+    /// Dial a TPM backend that hasn't connected yet. `None` selects the
+    /// first configured TPM device.
+    TpmReconnect(Option<String>),
+
+    /// Query a TPM device's establishment flag. `None` selects the first
+    /// configured TPM device.
+    TpmEstablishment(Option<String>),
+
+    /// Reset a TPM device's establishment flag. `None` selects the first
+    /// configured TPM device.
+    TpmResetEstablishedFlag(Option<String>),
+
+    /// Mark a TPM device's OS handoff point reached. `None` selects the
+    /// first configured TPM device.
+    TpmOsHandoff(Option<String>),
+
+    /// Wipe a TPM backend's permanent state and reinitialize it. `None`
+    /// selects the first configured TPM device.
+    TpmResetState(Option<String>),
+
     /// Add VFIO device
     AddDevice(Arc<DeviceConfig>),
 
@@ -405,6 +537,13 @@ fn vm_action(
         Pause => ApiRequest::VmPause(response_sender),
         Resume => ApiRequest::VmResume(response_sender),
         Counters => ApiRequest::VmCounters(response_sender),
+        TpmInfo(id) => ApiRequest::VmTpmInfo(id, response_sender),
+        TpmEventLog => ApiRequest::VmTpmEventLog(response_sender),
+        TpmReconnect(id) => ApiRequest::VmTpmReconnect(id, response_sender),
+        TpmEstablishment(id) => ApiRequest::VmTpmEstablishment(id, response_sender),
+        TpmResetEstablishedFlag(id) => ApiRequest::VmTpmResetEstablishedFlag(id, response_sender),
+        TpmOsHandoff(id) => ApiRequest::VmTpmOsHandoff(id, response_sender),
+        TpmResetState(id) => ApiRequest::VmTpmResetState(id, response_sender),
         AddDevice(v) => ApiRequest::VmAddDevice(v, response_sender),
         AddDisk(v) => ApiRequest::VmAddDisk(v, response_sender),
         AddFs(v) => ApiRequest::VmAddFs(v, response_sender),
@@ -462,6 +601,61 @@ pub fn vm_counters(api_evt: EventFd, api_sender: Sender<ApiRequest>) -> ApiResul
     vm_action(api_evt, api_sender, VmAction::Counters)
 }
 
+pub fn vm_tpm_info(
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+    id: Option<String>,
+) -> ApiResult<Option<Body>> {
+    vm_action(api_evt, api_sender, VmAction::TpmInfo(id))
+}
+
+pub fn vm_tpm_event_log(
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+) -> ApiResult<Option<Body>> {
+    vm_action(api_evt, api_sender, VmAction::TpmEventLog)
+}
+
+pub fn vm_tpm_reconnect(
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+    id: Option<String>,
+) -> ApiResult<Option<Body>> {
+    vm_action(api_evt, api_sender, VmAction::TpmReconnect(id))
+}
+
+pub fn vm_tpm_establishment(
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+    id: Option<String>,
+) -> ApiResult<Option<Body>> {
+    vm_action(api_evt, api_sender, VmAction::TpmEstablishment(id))
+}
+
+pub fn vm_tpm_reset_established_flag(
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+    id: Option<String>,
+) -> ApiResult<Option<Body>> {
+    vm_action(api_evt, api_sender, VmAction::TpmResetEstablishedFlag(id))
+}
+
+pub fn vm_tpm_os_handoff(
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+    id: Option<String>,
+) -> ApiResult<Option<Body>> {
+    vm_action(api_evt, api_sender, VmAction::TpmOsHandoff(id))
+}
+
+pub fn vm_tpm_reset_state(
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+    id: Option<String>,
+) -> ApiResult<Option<Body>> {
+    vm_action(api_evt, api_sender, VmAction::TpmResetState(id))
+}
+
 pub fn vm_power_button(
     api_evt: EventFd,
     api_sender: Sender<ApiRequest>,
@@ -56,6 +56,11 @@ pub enum Error {
     ParseNetwork(OptionParserError),
     /// Error parsing RNG options
     ParseRng(OptionParserError),
+
+    /// Failed parsing --tpm
+    ParseTpm(OptionParserError),
+    /// --tpm was given but this binary was built without the "tpm" feature
+    TpmSupportNotCompiledIn,
     /// Error parsing balloon options
     ParseBalloon(OptionParserError),
     /// Error parsing filesystem parameters
@@ -131,6 +136,25 @@ pub enum ValidationError {
     TdxKernelSpecified,
     // Insuffient vCPUs for queues
     TooManyQueues,
+    /// TPM MMIO base address is not aligned to the TIS window size, or
+    /// overlaps another fixed platform device
+    InvalidTpmMmioBase(u64),
+    /// TPM irq is outside the range of GSIs the platform can route
+    InvalidTpmIrq(u32),
+    /// Two TPM devices were given the same id
+    DuplicateTpmId(String),
+    /// Two TPM devices were given the same explicit MMIO base address
+    DuplicateTpmMmioBase(u64),
+    /// `require-state-encryption` was set alongside `state_dir`: the
+    /// builtin simulator never reports `TPM_CONFIG_FLAG_STATE_ENCRYPTION`,
+    /// so this combination would always fail VM creation once the backend
+    /// connects.
+    TpmStateEncryptionRequiresSwtpm(String),
+    /// `iommu` was set on a TPM device: this device is always placed on the
+    /// fixed ISA/MMIO window rather than PCI, and the virtual IOMMU's
+    /// topology is addressed by PCI BDF, so there is no endpoint ID yet for
+    /// it to attach to.
+    TpmIommuNotSupported(String),
 }
 
 type ValidationResult<T> = std::result::Result<T, ValidationError>;
@@ -178,6 +202,44 @@ impl fmt::Display for ValidationError {
             TooManyQueues => {
                 write!(f, "Number of vCPUs is insufficient for number of queues")
             }
+            InvalidTpmMmioBase(b) => {
+                write!(
+                    f,
+                    "TPM MMIO base address {:#x} is misaligned or overlaps another platform device",
+                    b
+                )
+            }
+            InvalidTpmIrq(i) => {
+                write!(f, "TPM irq {} is outside the platform's routable GSI range", i)
+            }
+            DuplicateTpmId(id) => {
+                write!(f, "Two TPM devices were given the same id '{}'", id)
+            }
+            TpmStateEncryptionRequiresSwtpm(id) => {
+                write!(
+                    f,
+                    "TPM device '{}' sets require-state-encryption with state_dir: the builtin \
+                     simulator never reports encrypted-at-rest state, so this combination can \
+                     never succeed; use an external swtpm (\"socket\") instead",
+                    id
+                )
+            }
+            DuplicateTpmMmioBase(b) => {
+                write!(
+                    f,
+                    "Two TPM devices were given the same MMIO base address {:#x}",
+                    b
+                )
+            }
+            TpmIommuNotSupported(id) => {
+                write!(
+                    f,
+                    "TPM device '{}' sets iommu=on: this device is placed on the fixed ISA/MMIO \
+                     window rather than PCI, and the virtual IOMMU's topology is addressed by PCI \
+                     BDF, so it cannot be attached to one yet",
+                    id
+                )
+            }
         }
     }
 }
@@ -211,6 +273,11 @@ impl fmt::Display for Error {
             ParseNetwork(o) => write!(f, "Error parsing --net: {}", o),
             ParseDisk(o) => write!(f, "Error parsing --disk: {}", o),
             ParseRng(o) => write!(f, "Error parsing --rng: {}", o),
+            ParseTpm(o) => write!(f, "Error parsing --tpm: {}", o),
+            TpmSupportNotCompiledIn => write!(
+                f,
+                "\"--tpm\" was given but this binary was built without the \"tpm\" feature"
+            ),
             ParseBalloon(o) => write!(f, "Error parsing --balloon: {}", o),
             ParseRestore(o) => write!(f, "Error parsing --restore: {}", o),
             #[cfg(target_arch = "x86_64")]
@@ -240,6 +307,7 @@ pub struct VmParams<'a> {
     pub disks: Option<Vec<&'a str>>,
     pub net: Option<Vec<&'a str>>,
     pub rng: &'a str,
+    pub tpm: Option<Vec<&'a str>>,
     pub balloon: Option<&'a str>,
     pub fs: Option<Vec<&'a str>>,
     pub pmem: Option<Vec<&'a str>>,
@@ -276,6 +344,7 @@ impl<'a> VmParams<'a> {
         let pmem: Option<Vec<&str>> = args.values_of("pmem").map(|x| x.collect());
         let devices: Option<Vec<&str>> = args.values_of("device").map(|x| x.collect());
         let vsock: Option<&str> = args.value_of("vsock");
+        let tpm: Option<Vec<&str>> = args.values_of("tpm").map(|x| x.collect());
         #[cfg(target_arch = "x86_64")]
         let sgx_epc: Option<Vec<&str>> = args.values_of("sgx-epc").map(|x| x.collect());
         let numa: Option<Vec<&str>> = args.values_of("numa").map(|x| x.collect());
@@ -299,6 +368,7 @@ impl<'a> VmParams<'a> {
             console,
             devices,
             vsock,
+            tpm,
             #[cfg(target_arch = "x86_64")]
             sgx_epc,
             numa,
@@ -1198,6 +1268,730 @@ impl Default for RngConfig {
     }
 }
 
+/// Where to reach an external swtpm's control and data channels.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum TpmSocket {
+    /// Unix domain sockets: the data channel lives at the given path, and
+    /// the control channel is expected at the same path with a `.ctrl`
+    /// extension added.
+    Unix(PathBuf),
+    /// TCP, for a swtpm reachable over the network (e.g. a different
+    /// network namespace or host) rather than sharing a filesystem with
+    /// this VMM. `ctrl_port` is the control channel; by convention here the
+    /// data channel is the next port up.
+    Tcp { host: String, ctrl_port: u16 },
+}
+
+#[derive(Debug)]
+pub struct ParseTpmSocketError(String);
+
+impl fmt::Display for ParseTpmSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid TPM socket \"{}\": expected a path, or \"tcp:<host>:<ctrl_port>\"",
+            self.0
+        )
+    }
+}
+
+impl FromStr for TpmSocket {
+    type Err = ParseTpmSocketError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.strip_prefix("tcp:") {
+            Some(rest) => {
+                let (host, port) = rest
+                    .rsplit_once(':')
+                    .ok_or_else(|| ParseTpmSocketError(s.to_owned()))?;
+                let ctrl_port = port
+                    .parse()
+                    .map_err(|_| ParseTpmSocketError(s.to_owned()))?;
+                Ok(TpmSocket::Tcp {
+                    host: host.to_owned(),
+                    ctrl_port,
+                })
+            }
+            None => Ok(TpmSocket::Unix(PathBuf::from(s))),
+        }
+    }
+}
+
+/// How the device arbitrates among multiple localities competing for
+/// ownership via TIS `requestUse`; see
+/// `devices::legacy::TisArbitrationPolicy`, which this maps onto in
+/// `DeviceManager`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum TpmArbitrationPolicy {
+    LowestFirst,
+    Fifo,
+}
+
+impl Default for TpmArbitrationPolicy {
+    fn default() -> Self {
+        TpmArbitrationPolicy::LowestFirst
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseTpmArbitrationPolicyError {
+    InvalidValue(String),
+}
+
+impl FromStr for TpmArbitrationPolicy {
+    type Err = ParseTpmArbitrationPolicyError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lowest-first" => Ok(TpmArbitrationPolicy::LowestFirst),
+            "fifo" => Ok(TpmArbitrationPolicy::Fifo),
+            _ => Err(ParseTpmArbitrationPolicyError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+/// Which `TPM_SU` type to drive through the backend's own
+/// `TPM2_Shutdown`/`TPM2_Startup` around a guest reset; see
+/// `vtpm::shutdown::ShutdownType`, which this maps onto in `DeviceManager`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum TpmRebootShutdown {
+    Clear,
+    State,
+}
+
+#[derive(Debug)]
+pub enum ParseTpmRebootShutdownError {
+    InvalidValue(String),
+}
+
+impl FromStr for TpmRebootShutdown {
+    type Err = ParseTpmRebootShutdownError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clear" => Ok(TpmRebootShutdown::Clear),
+            "state" => Ok(TpmRebootShutdown::State),
+            _ => Err(ParseTpmRebootShutdownError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TpmConfig {
+    /// Identifies this TPM device in the config and API when more than one
+    /// is configured. Auto-generated (`_tpm0`, `_tpm1`, ...) when unset.
+    pub id: Option<String>,
+    /// Where to reach the external swtpm's control/data channels, either a
+    /// Unix socket path or a `tcp:<host>:<port>` endpoint. Mutually
+    /// exclusive with `state_dir`.
+    pub socket: Option<TpmSocket>,
+    /// State directory for the in-process TPM simulator. Mutually
+    /// exclusive with `socket`.
+    pub state_dir: Option<PathBuf>,
+    #[serde(default = "default_tpmconfig_max_locality")]
+    pub max_locality: u8,
+    /// Overrides the MMIO base address of the TIS window, to avoid a
+    /// conflict with another platform device. Defaults to
+    /// `arch::layout::TPM_START` when unset.
+    pub base_address: Option<u64>,
+    /// Overrides the irq used to signal the guest, instead of taking the
+    /// next one handed out by the platform's GSI allocator.
+    pub irq: Option<u32>,
+    /// Refuse to start the VM unless the backend reports that its
+    /// persisted state is encrypted at rest (swtpm's `CmdGetConfig`
+    /// `TPM_CONFIG_FLAG_STATE_ENCRYPTION`). Has no effect on the builtin
+    /// simulator, which never reports that flag.
+    #[serde(default)]
+    pub require_state_encryption: bool,
+    /// Path to a file holding the passphrase to supply alongside
+    /// `CmdGetStateBlob`/`CmdSetStateBlob` when snapshotting or restoring
+    /// this device, required when the backend's persisted state is
+    /// encrypted at rest. Read once at VM creation time; the passphrase
+    /// itself never appears in the config or API.
+    pub passphrase_file: Option<PathBuf>,
+    /// TPM2 command ordinals (e.g. `0x126` for `TPM2_Clear`) the backend
+    /// should refuse to execute, answering the guest with a clean
+    /// `TPM_RC_COMMAND_CODE` error instead of running them. Intended for
+    /// hardened multi-tenant hosts that want to take commands like
+    /// `TPM2_Clear` or NV writes off the table regardless of guest policy.
+    #[serde(default)]
+    pub deny_commands: Vec<u32>,
+    /// How long to wait for the backend to answer a dispatched command, in
+    /// milliseconds, before reporting `TPM_RC_CANCELED` to the guest instead
+    /// of leaving it polling STS against a wedged or unresponsive backend.
+    /// Defaults to `devices::legacy::tpm_tis`'s own built-in timeout when
+    /// unset.
+    pub command_timeout_ms: Option<u64>,
+    /// Path to a file to append a structured (JSON Lines) audit record to
+    /// for every command dispatched to this TPM: command ordinal, locality,
+    /// command/response sizes, response code, and timestamp. Intended for
+    /// compliance-driven deployments that need a durable record independent
+    /// of `--event-monitor-path`. Unset by default.
+    pub audit_log: Option<PathBuf>,
+    /// Path to a pcap capture file to append a record of every command and
+    /// response dispatched to this TPM, for offline inspection with
+    /// `tcpdump`/Wireshark when a guest's attestation flow fails and
+    /// reproducing it live isn't practical. Complements `audit_log`, whose
+    /// JSON Lines format is built for durable compliance records rather
+    /// than for loading into a packet analyzer. Unset by default.
+    pub pcap_trace: Option<PathBuf>,
+    /// Overrides the PCI-style vendor id reported at `TPM_TIS_REG_DID_VID`.
+    /// Defaults to this tree's own placeholder identity when unset.
+    pub vendor_id: Option<u16>,
+    /// Overrides the device id reported at `TPM_TIS_REG_DID_VID`, alongside
+    /// `vendor_id`.
+    pub device_id: Option<u16>,
+    /// Overrides the revision id reported at `TPM_TIS_REG_RID`.
+    pub revision_id: Option<u8>,
+    /// Path to a DER-encoded endorsement key certificate to provision into
+    /// the backend's NV storage at the standard RSA EK cert index, so guest
+    /// attestation flows find a valid cert without the guest having to
+    /// provision one itself. Read once at VM creation time. Unset by
+    /// default.
+    pub ek_cert: Option<PathBuf>,
+    /// Don't dial the external swtpm at VM creation time; connect lazily
+    /// instead, on the first guest command or an explicit `vm.tpm-reconnect`
+    /// API call. Lets the VM start even if swtpm hasn't finished starting
+    /// up yet, at the cost of skipping `require-state-encryption` and the
+    /// `passphrase_file`/state blob support check until the backend is
+    /// actually connected. Has no effect on `state_dir` (the builtin
+    /// simulator is never deferred).
+    #[serde(default)]
+    pub defer_connect: bool,
+    /// How many times to retry the initial dial (and any later reconnect)
+    /// of an external swtpm before giving up and failing VM creation.
+    /// Defaults to `vtpm::ReconnectPolicy`'s own default when unset. Has no
+    /// effect on `state_dir`.
+    pub connect_retries: Option<u32>,
+    /// Delay, in milliseconds, before the first retry of a failed dial;
+    /// each subsequent retry doubles it, up to `connect-max-backoff-ms`.
+    /// Defaults to `vtpm::ReconnectPolicy`'s own default when unset.
+    pub connect_backoff_ms: Option<u64>,
+    /// Upper bound, in milliseconds, on the exponential backoff delay
+    /// between dial retries. Defaults to `vtpm::ReconnectPolicy`'s own
+    /// default when unset.
+    pub connect_max_backoff_ms: Option<u64>,
+    /// Caps the guest-to-device command buffer size negotiated with the
+    /// backend, independent of `resp-buffer-size-max`. Still clamped to
+    /// whatever range the backend itself reports supporting. Defaults to
+    /// `devices::legacy::TPM_TIS_BUFFER_MAX` when unset; set higher for a
+    /// `swtpm` build that supports larger buffers, or lower to cap transfer
+    /// size in one direction without affecting the other.
+    pub cmd_buffer_size_max: Option<u32>,
+    /// Caps the device-to-guest response buffer size negotiated with the
+    /// backend. See `cmd-buffer-size-max`.
+    pub resp_buffer_size_max: Option<u32>,
+    /// Owning uid to apply to `state_dir` once the builtin simulator has
+    /// finished laying out its files, e.g. so a host that drops privileges
+    /// after VM creation can still reach its own TPM state. Has no effect
+    /// on `socket` (this process never creates the external swtpm's
+    /// sockets, only connects to them).
+    pub state_dir_uid: Option<u32>,
+    /// Owning gid to apply to `state_dir`. See `state-dir-uid`.
+    pub state_dir_gid: Option<u32>,
+    /// Permission bits (octal, e.g. `0700`) to apply to `state_dir`. Has no
+    /// effect on `socket`, see `state-dir-uid`.
+    pub state_dir_mode: Option<u32>,
+    /// Once the OS handoff point is reached (an explicit `vm.tpm-os-handoff`
+    /// API call, or automatically the first time locality 0 is granted),
+    /// permanently locks localities 1-3 out of every register write,
+    /// emulating platform firmware losing access to the TPM once control
+    /// passes to the guest OS. Has no effect on locality 0. Off by default.
+    #[serde(default)]
+    pub os_handoff_locking: bool,
+    /// Issue `TPM2_SelfTest(fullTest: YES)` through the backend once at VM
+    /// creation time, before the guest runs, instead of relying on the
+    /// guest's own driver to trigger it. The result is recorded the same
+    /// way a guest-initiated self test would be (see
+    /// `devices::legacy::tpm_tis`'s `self_test_done`). Off by default; has
+    /// no effect on a `defer-connect` backend that isn't connected yet.
+    #[serde(default)]
+    pub boot_self_test: bool,
+    /// Fail VM creation if `boot-self-test` doesn't complete successfully,
+    /// instead of merely recording the failure and letting the guest
+    /// discover it. Has no effect unless `boot-self-test` is also set.
+    #[serde(default)]
+    pub boot_self_test_required: bool,
+    /// How ownership is handed out among localities competing via TIS
+    /// `requestUse`: `lowest-first` (this device's historical behavior,
+    /// always granting the lowest-numbered pending locality) or `fifo`
+    /// (grant requests in arrival order, so no locality can starve
+    /// another). Defaults to `lowest-first`.
+    #[serde(default)]
+    pub arbitration_policy: TpmArbitrationPolicy,
+    /// Raises an event-monitor notification (in addition to the warning log
+    /// every mode already gets) whenever the guest writes to a TIS register
+    /// offset this device doesn't decode, to help a driver developer notice
+    /// a bad access without going looking for it in the log. Off by
+    /// default: such writes don't affect guest-visible behavior either way.
+    #[serde(default)]
+    pub strict_mode: bool,
+    /// Leaves the backend's permanent state blob (NVRAM, keys, PCRs, ...)
+    /// out of `vm.snapshot`, so a snapshot file doesn't embed TPM secrets
+    /// for deployments that don't want them there. Register state is still
+    /// captured as usual. A snapshot taken this way can only be restored
+    /// onto a TPM that is also configured with `exclude-secrets`; restoring
+    /// it anywhere else fails VM restore rather than silently handing the
+    /// guest a wiped TPM it never asked for. Off by default.
+    #[serde(default)]
+    pub exclude_secrets: bool,
+    /// Also registers the CRB register interface alongside TIS, with
+    /// `TPM_TIS_REG_INTERFACE_ID`'s `InterfaceSelector` arbitrating which
+    /// one currently decodes accesses to the locality window. For firmware
+    /// that initializes the TPM over CRB before handing off to an OS driver
+    /// that only speaks TIS (or vice versa). Off by default: a device
+    /// constructed this way only ever speaks TIS, as before CRB support
+    /// existed.
+    #[serde(default)]
+    pub crb_capable: bool,
+    /// Drives `TPM2_Shutdown`/`TPM2_Startup` through the backend itself
+    /// around every guest reset, instead of leaving that handshake to
+    /// firmware: `clear` for `TPM_SU_CLEAR` (PCR values reset, the same as
+    /// a real power cycle), `state` for `TPM_SU_STATE` (PCR values and
+    /// other volatile state survive the reboot). Unset by default, which
+    /// preserves this device's original behavior of trusting firmware to
+    /// issue `TPM2_Startup` on its own.
+    pub reboot_shutdown: Option<TpmRebootShutdown>,
+    /// Places this device behind the virtual IOMMU, translating every
+    /// guest-declared `xdata` DMA address through it the same way a virtio
+    /// device attached to the same IOMMU has its descriptor addresses
+    /// translated. Rejected at validation time for now: this device is
+    /// always placed on the fixed ISA/MMIO window rather than PCI (see
+    /// `devices::legacy::tpm_pci`'s own account of why), and the virtual
+    /// IOMMU's topology is addressed by PCI BDF, so there is no endpoint ID
+    /// yet for it to attach to. Off by default.
+    #[serde(default)]
+    pub iommu: bool,
+    /// Whether `TPM_TIS_REG_INTF_CAPABILITY` advertises interrupt support at
+    /// all, and `TPM_TIS_REG_INT_ENABLE` accepts any of the guest's
+    /// interrupt-type bits. Off forces a well-behaved guest driver to poll
+    /// `TPM_TIS_REG_STS` instead of requesting an interrupt, for
+    /// compatibility testing against drivers that are expected to run in
+    /// polling mode. On by default.
+    #[serde(default = "default_tpmconfig_interrupts_supported")]
+    pub interrupts_supported: bool,
+}
+
+fn default_tpmconfig_max_locality() -> u8 {
+    devices::legacy::TPM_DEFAULT_MAX_GUEST_LOCALITY
+}
+
+fn default_tpmconfig_interrupts_supported() -> bool {
+    true
+}
+
+/// Accepts either a decimal or a `0x`-prefixed hexadecimal integer, since
+/// MMIO base addresses are conventionally written in hex.
+struct HexOrDecimal(u64);
+
+impl FromStr for HexOrDecimal {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16)?,
+            None => s.parse()?,
+        };
+        Ok(HexOrDecimal(value))
+    }
+}
+
+impl TpmConfig {
+    pub const SYNTAX: &'static str = "TPM parameters \
+        \"socket=<swtpm_control_socket_path>|tcp:<host>:<ctrl_port>,state_dir=<simulator_state_dir>,\
+        max-locality=<0-3>,base-address=<mmio_base>,irq=<N>,id=<device_id>,\
+        require-state-encryption=on|off,passphrase_file=<path>,deny-commands=<ordinal>[,<ordinal>]*,\
+        command-timeout-ms=<N>,audit-log=<path>,pcap-trace=<path>,vendor-id=<id>,device-id=<id>,revision-id=<id>,\
+        ek-cert=<path>,defer-connect=on|off,connect-retries=<N>,connect-backoff-ms=<N>,\
+        connect-max-backoff-ms=<N>,cmd-buffer-size-max=<bytes>,resp-buffer-size-max=<bytes>,\
+        state-dir-uid=<uid>,state-dir-gid=<gid>,state-dir-mode=<octal_mode>,\
+        os-handoff-locking=on|off,boot-self-test=on|off,boot-self-test-required=on|off,\
+        arbitration-policy=lowest-first|fifo,strict-mode=on|off,exclude-secrets=on|off,\
+        crb-capable=on|off,reboot-shutdown=clear|state,interrupts-supported=on|off\". \
+        Exactly one of \"socket\" (external swtpm) or \"state_dir\" (built-in simulator) must be given. \
+        \"socket\" is either a Unix socket path (the control channel is the same path with a \".ctrl\" \
+        extension) or \"tcp:<host>:<ctrl_port>\" for a swtpm reachable over TCP, whose data channel is \
+        expected one port above \"ctrl_port\". \
+        \"id\" and \"base-address\" distinguish multiple TPM devices and may be omitted when only one \
+        is configured. \"require-state-encryption\" refuses to start the VM unless the backend reports \
+        its persisted state as encrypted at rest. \"passphrase_file\" supplies the passphrase needed to \
+        snapshot or restore an encrypted backend's state. \"deny-commands\" is a comma separated list \
+        of TPM2 command ordinals (decimal or \"0x\"-prefixed hex, e.g. \"0x126,0x129\") the backend \
+        refuses to execute. \"command-timeout-ms\" bounds how long a dispatched command may take \
+        before the guest is told it was cancelled instead of waiting on an unresponsive backend. \
+        \"audit-log\" appends a JSON Lines record of every command dispatched to this TPM \
+        (ordinal, locality, sizes, response code, timestamp) to the given file. \
+        \"pcap-trace\" appends a pcap capture record of every command and response dispatched to \
+        this TPM to the given file, for loading into `tcpdump`/Wireshark. \
+        \"vendor-id\", \"device-id\" and \"revision-id\" (decimal or \"0x\"-prefixed hex) override the \
+        hardware identity reported at the TIS DID_VID/RID registers, for guest attestation stacks that \
+        expect a specific TPM vendor's identity to be present. \
+        \"ek-cert\" provisions the DER-encoded endorsement key certificate at the given path into the \
+        backend's NV storage at VM creation, so guest attestation flows find a valid EK cert. \
+        \"defer-connect\" skips dialing swtpm at VM creation time, connecting lazily on first guest \
+        command or an explicit \"vm.tpm-reconnect\" instead, so VM creation doesn't fail just because \
+        swtpm hasn't started listening yet. \
+        \"connect-retries\" caps how many times the initial dial (and any later reconnect) of an \
+        external swtpm is retried before giving up; \"connect-backoff-ms\" is the delay before the \
+        first retry, doubling on each subsequent one up to \"connect-max-backoff-ms\". \
+        \"cmd-buffer-size-max\" and \"resp-buffer-size-max\" independently cap the guest-to-device \
+        command and device-to-guest response buffer sizes negotiated with the backend, for a swtpm \
+        build that supports larger-than-default buffers in one or both directions. \
+        \"state-dir-uid\", \"state-dir-gid\" and \"state-dir-mode\" (octal) are applied to \"state_dir\" \
+        once the builtin simulator has created its files there, for a host that drops privileges after \
+        VM creation. \"os-handoff-locking\" permanently locks localities 1-3 out of every register \
+        write once the OS handoff point is reached, either via an explicit \"vm.tpm-os-handoff\" API \
+        call or automatically the first time locality 0 is granted. \
+        \"boot-self-test\" issues \"TPM2_SelfTest(fullTest: YES)\" through the backend once at VM \
+        creation time instead of waiting on the guest's own driver to run it. \
+        \"boot-self-test-required\" fails VM creation if that self test doesn't complete \
+        successfully; it has no effect unless \"boot-self-test\" is also set. \
+        \"arbitration-policy\" chooses how ownership is handed out among localities competing via \
+        TIS \"requestUse\": \"lowest-first\" (the default) always grants the lowest-numbered pending \
+        locality, while \"fifo\" grants requests in the order they arrived. \
+        \"strict-mode\" additionally raises an event-monitor notification whenever the guest writes \
+        to a register offset this device doesn't decode, to help catch bad driver accesses; off by \
+        default, since such writes don't otherwise affect guest-visible behavior. \
+        \"exclude-secrets\" leaves the backend's permanent state blob out of \"vm.snapshot\", so a \
+        snapshot file doesn't embed TPM secrets; a snapshot taken this way can only be restored onto \
+        a TPM also configured with \"exclude-secrets\", and fails VM restore otherwise. \
+        \"crb-capable\" also registers the CRB register interface alongside TIS, arbitrated by \
+        \"TPM_TIS_REG_INTERFACE_ID\"'s InterfaceSelector, for firmware that initializes the TPM over \
+        CRB before handing off to an OS driver that only speaks TIS (or vice versa); off by default. \
+        \"reboot-shutdown\" drives \"TPM2_Shutdown\"/\"TPM2_Startup\" through the backend itself around \
+        every guest reset instead of leaving that handshake to firmware: \"clear\" resets PCR values \
+        the same as a real power cycle, \"state\" carries them across the reboot; unset by default, \
+        which trusts firmware to issue \"TPM2_Startup\" on its own. \
+        \"iommu\" places this device behind the virtual IOMMU; currently always rejected, since this \
+        device is placed on the fixed ISA/MMIO window rather than PCI and the virtual IOMMU's \
+        topology is addressed by PCI BDF. \
+        \"interrupts-supported\" controls whether \"TPM_TIS_REG_INTF_CAPABILITY\" advertises \
+        interrupt support and \"TPM_TIS_REG_INT_ENABLE\" accepts any of the guest's interrupt-type \
+        bits; off forces a well-behaved guest driver to poll \"TPM_TIS_REG_STS\" instead, for \
+        compatibility testing against drivers expected to run in polling mode. On by default.";
+
+    pub fn parse(tpm: &str) -> Result<Self> {
+        if !cfg!(feature = "tpm") {
+            return Err(Error::TpmSupportNotCompiledIn);
+        }
+
+        let mut parser = OptionParser::new();
+        parser
+            .add("socket")
+            .add("state_dir")
+            .add("max-locality")
+            .add("base-address")
+            .add("irq")
+            .add("id")
+            .add("require-state-encryption")
+            .add("passphrase_file")
+            .add("deny-commands")
+            .add("command-timeout-ms")
+            .add("audit-log")
+            .add("pcap-trace")
+            .add("vendor-id")
+            .add("device-id")
+            .add("revision-id")
+            .add("ek-cert")
+            .add("defer-connect")
+            .add("connect-retries")
+            .add("connect-backoff-ms")
+            .add("connect-max-backoff-ms")
+            .add("cmd-buffer-size-max")
+            .add("resp-buffer-size-max")
+            .add("state-dir-uid")
+            .add("state-dir-gid")
+            .add("state-dir-mode")
+            .add("os-handoff-locking")
+            .add("boot-self-test")
+            .add("boot-self-test-required")
+            .add("arbitration-policy")
+            .add("strict-mode")
+            .add("exclude-secrets")
+            .add("crb-capable")
+            .add("reboot-shutdown")
+            .add("iommu")
+            .add("interrupts-supported");
+        parser.parse(tpm).map_err(Error::ParseTpm)?;
+
+        let id = parser.get("id");
+        let socket = parser
+            .convert::<TpmSocket>("socket")
+            .map_err(Error::ParseTpm)?;
+        let state_dir = parser.get("state_dir").map(PathBuf::from);
+        if socket.is_some() == state_dir.is_some() {
+            return Err(Error::ParseTpm(OptionParserError::InvalidSyntax(
+                tpm.to_owned(),
+            )));
+        }
+        let max_locality = parser
+            .convert::<u8>("max-locality")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(devices::legacy::TPM_DEFAULT_MAX_GUEST_LOCALITY);
+        let base_address = parser
+            .convert::<HexOrDecimal>("base-address")
+            .map_err(Error::ParseTpm)?
+            .map(|v| v.0);
+        let irq = parser.convert::<u32>("irq").map_err(Error::ParseTpm)?;
+        let require_state_encryption = parser
+            .convert::<Toggle>("require-state-encryption")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let passphrase_file = parser.get("passphrase_file").map(PathBuf::from);
+        let deny_commands = parser
+            .get("deny-commands")
+            .map(|list| {
+                list.split(',')
+                    .map(|ordinal| HexOrDecimal::from_str(ordinal).map(|v| v.0 as u32))
+                    .collect::<std::result::Result<Vec<u32>, _>>()
+            })
+            .transpose()
+            .map_err(|_| Error::ParseTpm(OptionParserError::InvalidSyntax(tpm.to_owned())))?
+            .unwrap_or_default();
+        let command_timeout_ms = parser
+            .convert::<u64>("command-timeout-ms")
+            .map_err(Error::ParseTpm)?;
+        let audit_log = parser.get("audit-log").map(PathBuf::from);
+        let pcap_trace = parser.get("pcap-trace").map(PathBuf::from);
+        let vendor_id = parser
+            .convert::<HexOrDecimal>("vendor-id")
+            .map_err(Error::ParseTpm)?
+            .map(|v| v.0 as u16);
+        let device_id = parser
+            .convert::<HexOrDecimal>("device-id")
+            .map_err(Error::ParseTpm)?
+            .map(|v| v.0 as u16);
+        let revision_id = parser
+            .convert::<HexOrDecimal>("revision-id")
+            .map_err(Error::ParseTpm)?
+            .map(|v| v.0 as u8);
+        let ek_cert = parser.get("ek-cert").map(PathBuf::from);
+        let defer_connect = parser
+            .convert::<Toggle>("defer-connect")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let connect_retries = parser
+            .convert::<u32>("connect-retries")
+            .map_err(Error::ParseTpm)?;
+        let connect_backoff_ms = parser
+            .convert::<u64>("connect-backoff-ms")
+            .map_err(Error::ParseTpm)?;
+        let connect_max_backoff_ms = parser
+            .convert::<u64>("connect-max-backoff-ms")
+            .map_err(Error::ParseTpm)?;
+        let cmd_buffer_size_max = parser
+            .convert::<u32>("cmd-buffer-size-max")
+            .map_err(Error::ParseTpm)?;
+        let resp_buffer_size_max = parser
+            .convert::<u32>("resp-buffer-size-max")
+            .map_err(Error::ParseTpm)?;
+        let state_dir_uid = parser
+            .convert::<u32>("state-dir-uid")
+            .map_err(Error::ParseTpm)?;
+        let state_dir_gid = parser
+            .convert::<u32>("state-dir-gid")
+            .map_err(Error::ParseTpm)?;
+        let state_dir_mode = parser
+            .get("state-dir-mode")
+            .map(|v| u32::from_str_radix(&v, 8))
+            .transpose()
+            .map_err(|_| Error::ParseTpm(OptionParserError::InvalidSyntax(tpm.to_owned())))?;
+        let os_handoff_locking = parser
+            .convert::<Toggle>("os-handoff-locking")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let boot_self_test = parser
+            .convert::<Toggle>("boot-self-test")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let boot_self_test_required = parser
+            .convert::<Toggle>("boot-self-test-required")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let arbitration_policy = parser
+            .convert("arbitration-policy")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or_default();
+        let strict_mode = parser
+            .convert::<Toggle>("strict-mode")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let exclude_secrets = parser
+            .convert::<Toggle>("exclude-secrets")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let crb_capable = parser
+            .convert::<Toggle>("crb-capable")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let reboot_shutdown = parser.convert("reboot-shutdown").map_err(Error::ParseTpm)?;
+        let iommu = parser
+            .convert::<Toggle>("iommu")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let interrupts_supported = parser
+            .convert::<Toggle>("interrupts-supported")
+            .map_err(Error::ParseTpm)?
+            .unwrap_or(Toggle(true))
+            .0;
+
+        Ok(TpmConfig {
+            id,
+            socket,
+            state_dir,
+            max_locality,
+            base_address,
+            irq,
+            require_state_encryption,
+            passphrase_file,
+            deny_commands,
+            command_timeout_ms,
+            audit_log,
+            pcap_trace,
+            vendor_id,
+            device_id,
+            revision_id,
+            ek_cert,
+            defer_connect,
+            connect_retries,
+            connect_backoff_ms,
+            connect_max_backoff_ms,
+            cmd_buffer_size_max,
+            resp_buffer_size_max,
+            state_dir_uid,
+            state_dir_gid,
+            state_dir_mode,
+            os_handoff_locking,
+            boot_self_test,
+            boot_self_test_required,
+            arbitration_policy,
+            strict_mode,
+            exclude_secrets,
+            crb_capable,
+            reboot_shutdown,
+            iommu,
+            interrupts_supported,
+        })
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn validate(&self) -> ValidationResult<()> {
+        if let Some(base) = self.base_address {
+            let size = arch::layout::TPM_SIZE;
+            let end = base
+                .checked_add(size)
+                .ok_or(ValidationError::InvalidTpmMmioBase(base))?;
+
+            if base % 0x1000 != 0 {
+                return Err(ValidationError::InvalidTpmMmioBase(base));
+            }
+
+            let reserved_start = arch::layout::MEM_32BIT_RESERVED_START.0;
+            let reserved_end = reserved_start + arch::layout::MEM_32BIT_RESERVED_SIZE;
+            if base < reserved_start || end > reserved_end {
+                return Err(ValidationError::InvalidTpmMmioBase(base));
+            }
+
+            // Other fixed platform windows within the 32-bit reserved range
+            // that the requested TPM window must not overlap. The APIC and
+            // KVM TSS area don't have their own size constants, so a
+            // conservative page-granular size is assumed for each.
+            let fixed_windows: &[(u64, u64)] = &[
+                (
+                    arch::layout::MEM_32BIT_DEVICES_START.0,
+                    arch::layout::MEM_32BIT_DEVICES_SIZE,
+                ),
+                (
+                    arch::layout::PCI_MMCONFIG_START.0,
+                    arch::layout::PCI_MMCONFIG_SIZE,
+                ),
+                (arch::layout::IOAPIC_START.0, arch::layout::IOAPIC_SIZE),
+                (arch::layout::APIC_START.0, 0x1000),
+                (arch::layout::KVM_TSS_ADDRESS.0, 0x3000),
+            ];
+
+            for &(win_start, win_size) in fixed_windows {
+                let win_end = win_start + win_size;
+                if base < win_end && end > win_start {
+                    return Err(ValidationError::InvalidTpmMmioBase(base));
+                }
+            }
+        }
+
+        if let Some(irq) = self.irq {
+            let max_irq = devices::ioapic::NUM_IOAPIC_PINS as u32;
+            if irq == 0 || irq >= max_irq {
+                return Err(ValidationError::InvalidTpmIrq(irq));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn validate(&self) -> ValidationResult<()> {
+        if let Some(base) = self.base_address {
+            let size = arch::layout::TPM_SIZE;
+            let end = base
+                .checked_add(size)
+                .ok_or(ValidationError::InvalidTpmMmioBase(base))?;
+
+            if base % 0x1000 != 0 {
+                return Err(ValidationError::InvalidTpmMmioBase(base));
+            }
+
+            if base < arch::layout::MAPPED_IO_START || end > arch::layout::RAM_64BIT_START {
+                return Err(ValidationError::InvalidTpmMmioBase(base));
+            }
+
+            // Other fixed platform windows that the requested TPM window
+            // must not overlap.
+            let fixed_windows: &[(u64, u64)] = &[
+                (arch::layout::LEGACY_SERIAL_MAPPED_IO_START, 0x1000),
+                (arch::layout::LEGACY_RTC_MAPPED_IO_START, 0x1000),
+                (arch::layout::LEGACY_GPIO_MAPPED_IO_START, 0x1000),
+                (
+                    arch::layout::MEM_PCI_IO_START.0,
+                    arch::layout::MEM_PCI_IO_SIZE,
+                ),
+                (
+                    arch::layout::MEM_32BIT_DEVICES_START.0,
+                    arch::layout::MEM_32BIT_DEVICES_SIZE,
+                ),
+                (
+                    arch::layout::PCI_MMCONFIG_START.0,
+                    arch::layout::PCI_MMCONFIG_SIZE,
+                ),
+            ];
+
+            for &(win_start, win_size) in fixed_windows {
+                let win_end = win_start + win_size;
+                if base < win_end && end > win_start {
+                    return Err(ValidationError::InvalidTpmMmioBase(base));
+                }
+            }
+        }
+
+        if let Some(irq) = self.irq {
+            if irq < arch::layout::IRQ_BASE || irq >= arch::layout::IRQ_BASE + arch::layout::IRQ_NUM
+            {
+                return Err(ValidationError::InvalidTpmIrq(irq));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct BalloonConfig {
     pub size: u64,
@@ -1747,6 +2541,7 @@ pub struct VmConfig {
     pub console: ConsoleConfig,
     pub devices: Option<Vec<DeviceConfig>>,
     pub vsock: Option<VsockConfig>,
+    pub tpm: Option<Vec<TpmConfig>>,
     #[serde(default)]
     pub iommu: bool,
     #[cfg(target_arch = "x86_64")]
@@ -1851,6 +2646,34 @@ impl VmConfig {
             }
         }
 
+        if let Some(tpms) = &self.tpm {
+            let mut ids = std::collections::BTreeSet::new();
+            let mut base_addresses = std::collections::BTreeSet::new();
+            for tpm in tpms {
+                if let Some(id) = &tpm.id {
+                    if !ids.insert(id.clone()) {
+                        return Err(ValidationError::DuplicateTpmId(id.clone()));
+                    }
+                }
+                if let Some(base) = tpm.base_address {
+                    if !base_addresses.insert(base) {
+                        return Err(ValidationError::DuplicateTpmMmioBase(base));
+                    }
+                }
+                if tpm.require_state_encryption && tpm.state_dir.is_some() {
+                    return Err(ValidationError::TpmStateEncryptionRequiresSwtpm(
+                        tpm.id.clone().unwrap_or_else(|| "<unnamed>".to_owned()),
+                    ));
+                }
+                if tpm.iommu {
+                    return Err(ValidationError::TpmIommuNotSupported(
+                        tpm.id.clone().unwrap_or_else(|| "<unnamed>".to_owned()),
+                    ));
+                }
+                tpm.validate()?;
+            }
+        }
+
         Ok(())
     }
 
@@ -1984,6 +2807,15 @@ impl VmConfig {
         #[cfg(feature = "tdx")]
         let tdx = vm_params.tdx.map(TdxConfig::parse).transpose()?;
 
+        let mut tpm: Option<Vec<TpmConfig>> = None;
+        if let Some(tpm_list) = &vm_params.tpm {
+            let mut tpm_config_list = Vec::new();
+            for item in tpm_list.iter() {
+                tpm_config_list.push(TpmConfig::parse(item)?);
+            }
+            tpm = Some(tpm_config_list);
+        }
+
         let config = VmConfig {
             cpus: CpusConfig::parse(vm_params.cpus)?,
             memory: MemoryConfig::parse(vm_params.memory, vm_params.memory_zones)?,
@@ -2000,6 +2832,7 @@ impl VmConfig {
             console,
             devices,
             vsock,
+            tpm,
             iommu,
             #[cfg(target_arch = "x86_64")]
             sgx_epc,
@@ -2608,6 +3441,7 @@ mod tests {
             },
             devices: None,
             vsock: None,
+            tpm: None,
             iommu: false,
             #[cfg(target_arch = "x86_64")]
             sgx_epc: None,
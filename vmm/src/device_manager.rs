@@ -10,8 +10,8 @@
 //
 
 use crate::config::{
-    ConsoleOutputMode, DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, VhostMode,
-    VmConfig, VsockConfig,
+    ConsoleOutputMode, DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, TpmConfig,
+    TpmSocket, VhostMode, VmConfig, VsockConfig,
 };
 use crate::device_tree::{DeviceNode, DeviceTree};
 #[cfg(feature = "kvm")]
@@ -77,6 +77,7 @@ use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::path::PathBuf;
 use std::result;
 use std::sync::{Arc, Barrier, Mutex};
+use std::time::Duration;
 #[cfg(feature = "acpi")]
 use uuid::Uuid;
 #[cfg(feature = "kvm")]
@@ -98,7 +99,7 @@ use vm_memory::guest_memory::FileOffset;
 use vm_memory::GuestMemoryRegion;
 use vm_memory::{Address, GuestAddress, GuestUsize, MmapRegion};
 #[cfg(all(target_arch = "x86_64", feature = "cmos"))]
-use vm_memory::{GuestAddressSpace, GuestMemory};
+use vm_memory::{Bytes, GuestAddressSpace, GuestMemory};
 use vm_migration::{
     Migratable, MigratableError, Pausable, Snapshot, SnapshotDataSection, Snapshottable,
     Transportable,
@@ -127,9 +128,65 @@ const BALLOON_DEVICE_NAME: &str = "_balloon";
 const NET_DEVICE_NAME_PREFIX: &str = "_net";
 const PMEM_DEVICE_NAME_PREFIX: &str = "_pmem";
 const RNG_DEVICE_NAME: &str = "_rng";
+const TPM_DEVICE_NAME_PREFIX: &str = "_tpm";
 const VSOCK_DEVICE_NAME_PREFIX: &str = "_vsock";
 const WATCHDOG_DEVICE_NAME: &str = "_watchdog";
 
+/// Builds the dial retry/backoff policy for a TPM's external swtpm
+/// connection from `TpmConfig`'s optional overrides, falling back to
+/// `vtpm::ReconnectPolicy`'s own defaults for whichever knobs are unset.
+#[cfg(feature = "tpm")]
+fn tpm_reconnect_policy(tpm_config: &TpmConfig) -> vtpm::ReconnectPolicy {
+    let default = vtpm::ReconnectPolicy::default();
+    vtpm::ReconnectPolicy {
+        max_attempts: tpm_config.connect_retries.unwrap_or(default.max_attempts),
+        initial_delay: tpm_config
+            .connect_backoff_ms
+            .map(std::time::Duration::from_millis)
+            .unwrap_or(default.initial_delay),
+        max_delay: tpm_config
+            .connect_max_backoff_ms
+            .map(std::time::Duration::from_millis)
+            .unwrap_or(default.max_delay),
+    }
+}
+
+/// Builds the command/response buffer size caps for a TPM device from
+/// `TpmConfig`'s optional overrides, falling back to
+/// `devices::legacy::TpmBufferSizeLimits`'s own defaults for whichever
+/// direction is unset.
+#[cfg(feature = "tpm")]
+fn tpm_buffer_size_limits(tpm_config: &TpmConfig) -> devices::legacy::TpmBufferSizeLimits {
+    let default = devices::legacy::TpmBufferSizeLimits::default();
+    devices::legacy::TpmBufferSizeLimits {
+        cmd_max: tpm_config.cmd_buffer_size_max.unwrap_or(default.cmd_max),
+        resp_max: tpm_config.resp_buffer_size_max.unwrap_or(default.resp_max),
+    }
+}
+
+/// Maps `TpmConfig`'s own arbitration policy enum onto the device's, which
+/// exists as a separate type because `devices` doesn't otherwise depend on
+/// `serde` the way `TpmConfig`'s other fields need to.
+#[cfg(feature = "tpm")]
+fn tpm_arbitration_policy(tpm_config: &TpmConfig) -> devices::legacy::TisArbitrationPolicy {
+    match tpm_config.arbitration_policy {
+        crate::config::TpmArbitrationPolicy::LowestFirst => {
+            devices::legacy::TisArbitrationPolicy::LowestFirst
+        }
+        crate::config::TpmArbitrationPolicy::Fifo => devices::legacy::TisArbitrationPolicy::Fifo,
+    }
+}
+
+/// Maps `TpmConfig`'s own reboot-shutdown enum onto `vtpm`'s, for the same
+/// reason as `tpm_arbitration_policy`: `devices` doesn't depend on `serde`.
+#[cfg(feature = "tpm")]
+fn tpm_reboot_shutdown(tpm_config: &TpmConfig) -> Option<vtpm::shutdown::ShutdownType> {
+    tpm_config.reboot_shutdown.map(|policy| match policy {
+        crate::config::TpmRebootShutdown::Clear => vtpm::shutdown::ShutdownType::Clear,
+        crate::config::TpmRebootShutdown::State => vtpm::shutdown::ShutdownType::State,
+    })
+}
+
 const IOMMU_DEVICE_NAME: &str = "_iommu";
 
 const VIRTIO_PCI_DEVICE_NAME_PREFIX: &str = "_virtio-pci";
@@ -158,6 +215,64 @@ pub enum DeviceManagerError {
     /// Cannot create virtio-rng device
     CreateVirtioRng(io::Error),
 
+    /// Cannot create TPM backend
+    #[cfg(feature = "tpm")]
+    CreateTpmBackend(vtpm::Error),
+
+    /// Cannot zero-initialize the TPM measured-boot event log in guest memory
+    InitializeTpmLog(vm_memory::GuestMemoryError),
+
+    /// Cannot query the TPM backend's configuration flags
+    #[cfg(feature = "tpm")]
+    GetTpmConfig(vtpm::Error),
+
+    /// TPM was configured with "require-state-encryption=on" but the
+    /// backend did not report its state as encrypted at rest
+    TpmStateEncryptionNotEnabled,
+
+    /// Cannot read the TPM state-blob passphrase file
+    ReadTpmPassphrase(io::Error),
+
+    /// Cannot open the TPM command audit log file
+    #[cfg(feature = "tpm")]
+    OpenTpmAuditLog(io::Error),
+
+    /// Cannot open the TPM command pcap trace file
+    #[cfg(feature = "tpm")]
+    OpenTpmPcapTrace(io::Error),
+
+    /// Cannot read the TPM endorsement key certificate file
+    #[cfg(feature = "tpm")]
+    ReadTpmEkCert(io::Error),
+
+    /// Cannot query the TPM backend's control channel capabilities
+    #[cfg(feature = "tpm")]
+    GetTpmCapabilities(vtpm::Error),
+
+    /// TPM was configured with "passphrase_file" but the connected backend
+    /// does not support getting/setting state blobs, so an encrypted
+    /// snapshot of it could never be produced
+    #[cfg(feature = "tpm")]
+    TpmPassphraseRequiresStateBlobSupport,
+
+    /// Cannot reconnect a TPM backend that was configured with
+    /// "defer-connect=on"
+    #[cfg(feature = "tpm")]
+    TpmReconnect(devices::legacy::TpmTisError),
+
+    /// Cannot reset the TPM establishment flag
+    #[cfg(feature = "tpm")]
+    TpmResetEstablishedFlag(devices::legacy::TpmTisError),
+
+    /// TPM was configured with "boot-self-test-required=on" and the backend
+    /// failed "TPM2_SelfTest(fullTest: YES)" at VM creation time
+    #[cfg(feature = "tpm")]
+    TpmBootSelfTestFailed(vtpm::Error),
+
+    /// Cannot wipe and reinitialize a TPM backend's permanent state
+    #[cfg(feature = "tpm")]
+    TpmResetState(devices::legacy::TpmTisError),
+
     /// Cannot create virtio-fs device
     CreateVirtioFs(virtio_devices::vhost_user::Error),
 
@@ -906,6 +1021,17 @@ pub struct DeviceManager {
     // Possible handle to the virtio-balloon device
     balloon: Option<Arc<Mutex<virtio_devices::Balloon>>>,
 
+    // Handles to the configured TPM devices, kept around so their state can
+    // be queried for debugging without having to downcast a `dyn
+    // BusDevice`. Indexed by the device id assigned in `TpmConfig`.
+    #[cfg(feature = "tpm")]
+    tpm_devices: Vec<(String, Arc<Mutex<devices::legacy::TPMIsa>>)>,
+
+    // Guest memory range reserved for the TPM measured-boot event log, if a
+    // TPM device was configured. x86_64 only: described to guest firmware
+    // through the ACPI TPM2 table's LAML/LASA fields.
+    tpm_log_region: Option<(GuestAddress, u64)>,
+
     // Virtio Device activation EventFd to allow the VMM thread to trigger device
     // activation and thus start the threads from the VMM thread
     activate_evt: EventFd,
@@ -994,6 +1120,9 @@ impl DeviceManager {
             #[cfg(feature = "acpi")]
             numa_nodes,
             balloon: None,
+            #[cfg(feature = "tpm")]
+            tpm_devices: Vec::new(),
+            tpm_log_region: None,
             activate_evt: activate_evt
                 .try_clone()
                 .map_err(DeviceManagerError::EventFd)?,
@@ -1068,6 +1197,7 @@ impl DeviceManager {
             self.reset_evt
                 .try_clone()
                 .map_err(DeviceManagerError::EventFd)?,
+            &legacy_interrupt_manager,
         )?;
 
         #[cfg(target_arch = "aarch64")]
@@ -1384,7 +1514,11 @@ impl DeviceManager {
     }
 
     #[cfg(target_arch = "x86_64")]
-    fn add_legacy_devices(&mut self, reset_evt: EventFd) -> DeviceManagerResult<()> {
+    fn add_legacy_devices(
+        &mut self,
+        reset_evt: EventFd,
+        interrupt_manager: &Arc<dyn InterruptManager<GroupConfig = LegacyIrqGroupConfig>>,
+    ) -> DeviceManagerResult<()> {
         // Add a shutdown device (i8042)
         let i8042 = Arc::new(Mutex::new(devices::legacy::I8042Device::new(reset_evt)));
 
@@ -1436,6 +1570,264 @@ impl DeviceManager {
                 .map_err(DeviceManagerError::BusError)?;
         }
 
+        #[cfg(feature = "tpm")]
+        {
+            let tpm_configs = self.config.lock().unwrap().tpm.clone();
+            if let Some(tpm_configs) = tpm_configs {
+                for (i, mut tpm_config) in tpm_configs.into_iter().enumerate() {
+                    let id = if let Some(id) = &tpm_config.id {
+                        id.clone()
+                    } else {
+                        let id = self.next_device_name(TPM_DEVICE_NAME_PREFIX)?;
+                        tpm_config.id = Some(id.clone());
+                        id
+                    };
+
+                    let (backend_config, backend_kind) = if let Some(state_dir) =
+                        tpm_config.state_dir
+                    {
+                        (
+                            devices::legacy::TPMBackendConfig::Builtin {
+                                state_dir,
+                                state_dir_uid: tpm_config.state_dir_uid,
+                                state_dir_gid: tpm_config.state_dir_gid,
+                                state_dir_mode: tpm_config.state_dir_mode,
+                            },
+                            "builtin".to_owned(),
+                        )
+                    } else if let Some(socket) = tpm_config.socket {
+                        let reconnect_policy = tpm_reconnect_policy(&tpm_config);
+                        (
+                            match socket {
+                                TpmSocket::Unix(data_path) => {
+                                    devices::legacy::TPMBackendConfig::Emulator {
+                                        ctrl_path: data_path.with_extension("ctrl"),
+                                        data_path,
+                                        defer_connect: tpm_config.defer_connect,
+                                        reconnect_policy,
+                                    }
+                                }
+                                TpmSocket::Tcp { host, ctrl_port } => {
+                                    devices::legacy::TPMBackendConfig::EmulatorTcp {
+                                        host,
+                                        ctrl_port,
+                                        defer_connect: tpm_config.defer_connect,
+                                        reconnect_policy,
+                                    }
+                                }
+                            },
+                            "emulator".to_owned(),
+                        )
+                    } else {
+                        unreachable!(
+                            "TpmConfig::parse() guarantees one of socket/state_dir is set"
+                        );
+                    };
+
+                    let ek_cert = tpm_config
+                        .ek_cert
+                        .as_ref()
+                        .map(std::fs::read)
+                        .transpose()
+                        .map_err(DeviceManagerError::ReadTpmEkCert)?;
+
+                    let (backend, startup_failed) = devices::legacy::new_tpm_backend(
+                        backend_config,
+                        &tpm_config.deny_commands,
+                        ek_cert.as_deref(),
+                    )
+                    .map_err(DeviceManagerError::CreateTpmBackend)
+                    .map(|(backend, started)| (backend, !started))?;
+
+                    // A "defer-connect=on" backend that hasn't dialed swtpm
+                    // yet can't answer either of these checks; both are
+                    // skipped until a `vm.tpm-reconnect` (or the guest's own
+                    // first command) actually connects it; that is the
+                    // tradeoff of not blocking VM creation on swtpm being up.
+                    let backend_connected = backend.lock().unwrap().is_connected();
+
+                    if tpm_config.require_state_encryption && backend_connected {
+                        let config = backend
+                            .lock()
+                            .unwrap()
+                            .get_config()
+                            .map_err(DeviceManagerError::GetTpmConfig)?;
+                        if config.flags & vtpm::ptm::TPM_CONFIG_FLAG_STATE_ENCRYPTION == 0 {
+                            return Err(DeviceManagerError::TpmStateEncryptionNotEnabled);
+                        }
+                    }
+
+                    if tpm_config.passphrase_file.is_some() && backend_connected {
+                        let backend_capabilities = backend
+                            .lock()
+                            .unwrap()
+                            .capabilities()
+                            .map_err(DeviceManagerError::GetTpmCapabilities)?;
+                        if !backend_capabilities.supports_stateblob() {
+                            return Err(DeviceManagerError::TpmPassphraseRequiresStateBlobSupport);
+                        }
+                    }
+
+                    // Same "defer-connect=on" caveat as the checks above: a
+                    // backend that isn't connected yet can't run a self test,
+                    // so it is simply skipped rather than treated as a
+                    // failure the guest or "boot-self-test-required" should
+                    // see.
+                    let boot_self_test_passed = if tpm_config.boot_self_test && backend_connected {
+                        match vtpm::run_self_test(&mut *backend.lock().unwrap()) {
+                            Ok(()) => Some(true),
+                            Err(e) if tpm_config.boot_self_test_required => {
+                                return Err(DeviceManagerError::TpmBootSelfTestFailed(e));
+                            }
+                            Err(_) => Some(false),
+                        }
+                    } else {
+                        None
+                    };
+
+                    // Best-effort, like the checks above: a backend that
+                    // isn't connected yet, or doesn't implement CmdGetInfo
+                    // at all (the built-in simulator), just reports nothing
+                    // rather than failing VM creation over a bug report
+                    // nicety.
+                    let backend_info = if backend_connected {
+                        backend.lock().unwrap().get_info().ok()
+                    } else {
+                        None
+                    };
+                    if let Some(ref info) = backend_info {
+                        info!(
+                            "TPM {}: backend version {}, build info: {}",
+                            id, info.version, info.build_info
+                        );
+                    }
+
+                    let passphrase = tpm_config
+                        .passphrase_file
+                        .as_ref()
+                        .map(std::fs::read)
+                        .transpose()
+                        .map_err(DeviceManagerError::ReadTpmPassphrase)?;
+
+                    // A user-requested irq bypasses the allocator entirely, so it is
+                    // the user's responsibility (checked only as far as
+                    // TpmConfig::validate()'s static range check) to pick one that
+                    // no other device will be handed out later.
+                    let tpm_irq = if let Some(irq) = tpm_config.irq {
+                        irq
+                    } else {
+                        self.address_manager
+                            .allocator
+                            .lock()
+                            .unwrap()
+                            .allocate_irq()
+                            .ok_or(DeviceManagerError::AllocateIrq)?
+                    };
+
+                    let interrupt_group = interrupt_manager
+                        .create_group(LegacyIrqGroupConfig {
+                            irq: tpm_irq as InterruptIndex,
+                        })
+                        .map_err(DeviceManagerError::CreateInterruptGroup)?;
+
+                    let tpm_device = Arc::new(Mutex::new(devices::legacy::TPMIsa::new(
+                        id.clone(),
+                        backend,
+                        interrupt_group,
+                        tpm_irq,
+                        tpm_config.max_locality,
+                        backend_kind,
+                        passphrase,
+                        tpm_config.command_timeout_ms.map(Duration::from_millis),
+                        devices::legacy::TpmDeviceIdentity {
+                            vendor_id: tpm_config
+                                .vendor_id
+                                .unwrap_or(devices::legacy::TpmDeviceIdentity::default().vendor_id),
+                            device_id: tpm_config
+                                .device_id
+                                .unwrap_or(devices::legacy::TpmDeviceIdentity::default().device_id),
+                            revision_id: tpm_config
+                                .revision_id
+                                .unwrap_or(devices::legacy::TpmDeviceIdentity::default().revision_id),
+                        },
+                        tpm_buffer_size_limits(&tpm_config),
+                        startup_failed,
+                        tpm_config.os_handoff_locking,
+                        boot_self_test_passed,
+                        backend_info,
+                        tpm_arbitration_policy(&tpm_config),
+                        tpm_config.strict_mode,
+                        tpm_config.exclude_secrets,
+                        tpm_config.crb_capable,
+                        tpm_reboot_shutdown(&tpm_config),
+                        tpm_config.interrupts_supported,
+                    )));
+                    tpm_device
+                        .lock()
+                        .unwrap()
+                        .set_memory(self.memory_manager.lock().unwrap().guest_memory());
+
+                    if let Some(audit_log_path) = tpm_config.audit_log.clone() {
+                        let audit_log = devices::legacy::TpmAuditLog::new(audit_log_path)
+                            .map_err(DeviceManagerError::OpenTpmAuditLog)?;
+                        tpm_device.lock().unwrap().set_audit_log(audit_log);
+                    }
+
+                    if let Some(pcap_trace_path) = tpm_config.pcap_trace.clone() {
+                        let pcap_trace = devices::legacy::TpmPcapTrace::new(pcap_trace_path)
+                            .map_err(DeviceManagerError::OpenTpmPcapTrace)?;
+                        tpm_device.lock().unwrap().set_pcap_trace(pcap_trace);
+                    }
+
+                    self.bus_devices
+                        .push(Arc::clone(&tpm_device) as Arc<Mutex<dyn BusDevice>>);
+
+                    // Unset base addresses are spaced out by index so that
+                    // multiple TPM devices don't collide on the default window.
+                    let tpm_mmio_base = tpm_config.base_address.unwrap_or(
+                        arch::layout::TPM_START.0 + (i as u64) * arch::layout::TPM_SIZE,
+                    );
+
+                    self.address_manager
+                        .mmio_bus
+                        .insert(
+                            Arc::clone(&tpm_device),
+                            tpm_mmio_base,
+                            arch::layout::TPM_SIZE,
+                        )
+                        .map_err(DeviceManagerError::BusError)?;
+
+                    self.device_tree
+                        .lock()
+                        .unwrap()
+                        .insert(id.clone(), device_node!(id, tpm_device));
+
+                    self.tpm_devices.push((id, tpm_device));
+
+                    // Reserve and zero the measured-boot event log area, shared
+                    // by all configured TPM devices. Firmware appends
+                    // TCG_PCR_EVENT2 records to it directly as guest memory, and
+                    // its location is advertised to firmware/OS through the
+                    // ACPI TPM2 table's LAML/LASA fields (see
+                    // `acpi::create_acpi_tables`).
+                    if self.tpm_log_region.is_none() {
+                        self.memory_manager
+                            .lock()
+                            .unwrap()
+                            .guest_memory()
+                            .memory()
+                            .write_slice(
+                                &vec![0u8; arch::layout::TPM_LOG_SIZE as usize],
+                                arch::layout::TPM_LOG_START,
+                            )
+                            .map_err(DeviceManagerError::InitializeTpmLog)?;
+                        self.tpm_log_region =
+                            Some((arch::layout::TPM_LOG_START, arch::layout::TPM_LOG_SIZE));
+                    }
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -1476,6 +1868,7 @@ impl DeviceManager {
             MmioDeviceInfo {
                 addr: addr.0,
                 irq: rtc_irq,
+                length: MMIO_LEN,
             },
         );
 
@@ -1517,6 +1910,7 @@ impl DeviceManager {
             MmioDeviceInfo {
                 addr: addr.0,
                 irq: gpio_irq,
+                length: MMIO_LEN,
             },
         );
 
@@ -1525,6 +1919,246 @@ impl DeviceManager {
             .unwrap()
             .insert(id.clone(), device_node!(id, gpio_device));
 
+        // Add TPM devices
+        #[cfg(feature = "tpm")]
+        {
+            let tpm_configs = self.config.lock().unwrap().tpm.clone();
+            if let Some(tpm_configs) = tpm_configs {
+                for (i, mut tpm_config) in tpm_configs.into_iter().enumerate() {
+                    let id = if let Some(id) = &tpm_config.id {
+                        id.clone()
+                    } else {
+                        let id = self.next_device_name(TPM_DEVICE_NAME_PREFIX)?;
+                        tpm_config.id = Some(id.clone());
+                        id
+                    };
+
+                    let (backend_config, backend_kind) = if let Some(state_dir) =
+                        tpm_config.state_dir
+                    {
+                        (
+                            devices::legacy::TPMBackendConfig::Builtin {
+                                state_dir,
+                                state_dir_uid: tpm_config.state_dir_uid,
+                                state_dir_gid: tpm_config.state_dir_gid,
+                                state_dir_mode: tpm_config.state_dir_mode,
+                            },
+                            "builtin".to_owned(),
+                        )
+                    } else if let Some(socket) = tpm_config.socket {
+                        let reconnect_policy = tpm_reconnect_policy(&tpm_config);
+                        (
+                            match socket {
+                                TpmSocket::Unix(data_path) => {
+                                    devices::legacy::TPMBackendConfig::Emulator {
+                                        ctrl_path: data_path.with_extension("ctrl"),
+                                        data_path,
+                                        defer_connect: tpm_config.defer_connect,
+                                        reconnect_policy,
+                                    }
+                                }
+                                TpmSocket::Tcp { host, ctrl_port } => {
+                                    devices::legacy::TPMBackendConfig::EmulatorTcp {
+                                        host,
+                                        ctrl_port,
+                                        defer_connect: tpm_config.defer_connect,
+                                        reconnect_policy,
+                                    }
+                                }
+                            },
+                            "emulator".to_owned(),
+                        )
+                    } else {
+                        unreachable!(
+                            "TpmConfig::parse() guarantees one of socket/state_dir is set"
+                        );
+                    };
+
+                    let ek_cert = tpm_config
+                        .ek_cert
+                        .as_ref()
+                        .map(std::fs::read)
+                        .transpose()
+                        .map_err(DeviceManagerError::ReadTpmEkCert)?;
+
+                    let (backend, startup_failed) = devices::legacy::new_tpm_backend(
+                        backend_config,
+                        &tpm_config.deny_commands,
+                        ek_cert.as_deref(),
+                    )
+                    .map_err(DeviceManagerError::CreateTpmBackend)
+                    .map(|(backend, started)| (backend, !started))?;
+
+                    // A "defer-connect=on" backend that hasn't dialed swtpm
+                    // yet can't answer either of these checks; both are
+                    // skipped until a `vm.tpm-reconnect` (or the guest's own
+                    // first command) actually connects it; that is the
+                    // tradeoff of not blocking VM creation on swtpm being up.
+                    let backend_connected = backend.lock().unwrap().is_connected();
+
+                    if tpm_config.require_state_encryption && backend_connected {
+                        let config = backend
+                            .lock()
+                            .unwrap()
+                            .get_config()
+                            .map_err(DeviceManagerError::GetTpmConfig)?;
+                        if config.flags & vtpm::ptm::TPM_CONFIG_FLAG_STATE_ENCRYPTION == 0 {
+                            return Err(DeviceManagerError::TpmStateEncryptionNotEnabled);
+                        }
+                    }
+
+                    if tpm_config.passphrase_file.is_some() && backend_connected {
+                        let backend_capabilities = backend
+                            .lock()
+                            .unwrap()
+                            .capabilities()
+                            .map_err(DeviceManagerError::GetTpmCapabilities)?;
+                        if !backend_capabilities.supports_stateblob() {
+                            return Err(DeviceManagerError::TpmPassphraseRequiresStateBlobSupport);
+                        }
+                    }
+
+                    // Same "defer-connect=on" caveat as the checks above: a
+                    // backend that isn't connected yet can't run a self test,
+                    // so it is simply skipped rather than treated as a
+                    // failure the guest or "boot-self-test-required" should
+                    // see.
+                    let boot_self_test_passed = if tpm_config.boot_self_test && backend_connected {
+                        match vtpm::run_self_test(&mut *backend.lock().unwrap()) {
+                            Ok(()) => Some(true),
+                            Err(e) if tpm_config.boot_self_test_required => {
+                                return Err(DeviceManagerError::TpmBootSelfTestFailed(e));
+                            }
+                            Err(_) => Some(false),
+                        }
+                    } else {
+                        None
+                    };
+
+                    // Best-effort, like the checks above: a backend that
+                    // isn't connected yet, or doesn't implement CmdGetInfo
+                    // at all (the built-in simulator), just reports nothing
+                    // rather than failing VM creation over a bug report
+                    // nicety.
+                    let backend_info = if backend_connected {
+                        backend.lock().unwrap().get_info().ok()
+                    } else {
+                        None
+                    };
+                    if let Some(ref info) = backend_info {
+                        info!(
+                            "TPM {}: backend version {}, build info: {}",
+                            id, info.version, info.build_info
+                        );
+                    }
+
+                    let passphrase = tpm_config
+                        .passphrase_file
+                        .as_ref()
+                        .map(std::fs::read)
+                        .transpose()
+                        .map_err(DeviceManagerError::ReadTpmPassphrase)?;
+
+                    let tpm_irq = if let Some(irq) = tpm_config.irq {
+                        irq
+                    } else {
+                        self.address_manager
+                            .allocator
+                            .lock()
+                            .unwrap()
+                            .allocate_irq()
+                            .ok_or(DeviceManagerError::AllocateIrq)?
+                    };
+
+                    let interrupt_group = interrupt_manager
+                        .create_group(LegacyIrqGroupConfig {
+                            irq: tpm_irq as InterruptIndex,
+                        })
+                        .map_err(DeviceManagerError::CreateInterruptGroup)?;
+
+                    let tpm_device = Arc::new(Mutex::new(devices::legacy::TPMIsa::new(
+                        id.clone(),
+                        backend,
+                        interrupt_group,
+                        tpm_irq,
+                        tpm_config.max_locality,
+                        backend_kind,
+                        passphrase,
+                        tpm_config.command_timeout_ms.map(Duration::from_millis),
+                        devices::legacy::TpmDeviceIdentity {
+                            vendor_id: tpm_config
+                                .vendor_id
+                                .unwrap_or(devices::legacy::TpmDeviceIdentity::default().vendor_id),
+                            device_id: tpm_config
+                                .device_id
+                                .unwrap_or(devices::legacy::TpmDeviceIdentity::default().device_id),
+                            revision_id: tpm_config
+                                .revision_id
+                                .unwrap_or(devices::legacy::TpmDeviceIdentity::default().revision_id),
+                        },
+                        tpm_buffer_size_limits(&tpm_config),
+                        startup_failed,
+                        tpm_config.os_handoff_locking,
+                        boot_self_test_passed,
+                        backend_info,
+                        tpm_arbitration_policy(&tpm_config),
+                        tpm_config.strict_mode,
+                        tpm_config.exclude_secrets,
+                        tpm_config.crb_capable,
+                        tpm_reboot_shutdown(&tpm_config),
+                        tpm_config.interrupts_supported,
+                    )));
+                    tpm_device
+                        .lock()
+                        .unwrap()
+                        .set_memory(self.memory_manager.lock().unwrap().guest_memory());
+
+                    if let Some(audit_log_path) = tpm_config.audit_log.clone() {
+                        let audit_log = devices::legacy::TpmAuditLog::new(audit_log_path)
+                            .map_err(DeviceManagerError::OpenTpmAuditLog)?;
+                        tpm_device.lock().unwrap().set_audit_log(audit_log);
+                    }
+
+                    if let Some(pcap_trace_path) = tpm_config.pcap_trace.clone() {
+                        let pcap_trace = devices::legacy::TpmPcapTrace::new(pcap_trace_path)
+                            .map_err(DeviceManagerError::OpenTpmPcapTrace)?;
+                        tpm_device.lock().unwrap().set_pcap_trace(pcap_trace);
+                    }
+
+                    self.bus_devices
+                        .push(Arc::clone(&tpm_device) as Arc<Mutex<dyn BusDevice>>);
+
+                    // Unset base addresses are spaced out by index so that
+                    // multiple TPM devices don't collide on the default window.
+                    let addr = GuestAddress(tpm_config.base_address.unwrap_or(
+                        arch::layout::LEGACY_TPM_MAPPED_IO_START
+                            + (i as u64) * arch::layout::TPM_SIZE,
+                    ));
+
+                    self.address_manager
+                        .mmio_bus
+                        .insert(Arc::clone(&tpm_device), addr.0, arch::layout::TPM_SIZE)
+                        .map_err(DeviceManagerError::BusError)?;
+
+                    self.id_to_dev_info.insert(
+                        (DeviceType::Tpm, id.clone()),
+                        MmioDeviceInfo {
+                            addr: addr.0,
+                            irq: tpm_irq,
+                            length: arch::layout::TPM_SIZE,
+                        },
+                    );
+
+                    self.device_tree
+                        .lock()
+                        .unwrap()
+                        .insert(id.clone(), device_node!(id, tpm_device));
+
+                    self.tpm_devices.push((id, tpm_device));
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -1620,6 +2254,7 @@ impl DeviceManager {
             MmioDeviceInfo {
                 addr: addr.0,
                 irq: serial_irq,
+                length: MMIO_LEN,
             },
         );
 
@@ -3436,9 +4071,132 @@ impl DeviceManager {
             }
         }
 
+        #[cfg(feature = "tpm")]
+        for (id, tpm_device) in &self.tpm_devices {
+            counters.insert(id.clone(), tpm_device.lock().unwrap().counters());
+        }
+
         counters
     }
 
+    // `id` selects which configured TPM device to query; `None` defaults to
+    // the first one, for backward compatibility with single-TPM configs.
+    #[cfg(feature = "tpm")]
+    pub fn tpm_info(&self, id: Option<&str>) -> Option<devices::legacy::TpmDeviceInfo> {
+        let tpm = match id {
+            Some(id) => self.tpm_devices.iter().find(|(i, _)| i == id),
+            None => self.tpm_devices.first(),
+        }?;
+        Some(tpm.1.lock().unwrap().info())
+    }
+
+    // swtpm does not expect TPM 2.0 state to survive a guest-triggered
+    // reset, so every locality's registers are cleared and the backend
+    // startup handshake is re-run alongside the rest of the VM's reset.
+    #[cfg(feature = "tpm")]
+    pub fn reset_tpm(&self) {
+        for (_, tpm) in &self.tpm_devices {
+            tpm.lock().unwrap().reset();
+        }
+    }
+
+    // `id` selects which configured TPM device to reconnect; `None` defaults
+    // to the first one, mirroring `DeviceManager::tpm_info`. `None` is also
+    // returned when no TPM device matching `id` is configured at all, so the
+    // caller can tell "no such device" apart from "reconnected but failed".
+    #[cfg(feature = "tpm")]
+    pub fn tpm_reconnect(&self, id: Option<&str>) -> Option<DeviceManagerResult<()>> {
+        let tpm = match id {
+            Some(id) => self.tpm_devices.iter().find(|(i, _)| i == id),
+            None => self.tpm_devices.first(),
+        }?;
+        Some(
+            tpm.1
+                .lock()
+                .unwrap()
+                .reconnect()
+                .map_err(DeviceManagerError::TpmReconnect),
+        )
+    }
+
+    // `id` selects which configured TPM device to reset the establishment
+    // flag on; `None` defaults to the first one, mirroring
+    // `DeviceManager::tpm_info`. `None` is also returned when no TPM device
+    // matching `id` is configured at all, so the caller can tell "no such
+    // device" apart from "reset but failed".
+    #[cfg(feature = "tpm")]
+    pub fn tpm_reset_established_flag(&self, id: Option<&str>) -> Option<DeviceManagerResult<()>> {
+        let tpm = match id {
+            Some(id) => self.tpm_devices.iter().find(|(i, _)| i == id),
+            None => self.tpm_devices.first(),
+        }?;
+        Some(
+            tpm.1
+                .lock()
+                .unwrap()
+                .reset_established_flag()
+                .map_err(DeviceManagerError::TpmResetEstablishedFlag),
+        )
+    }
+
+    // `id` selects which configured TPM device to mark the OS handoff point
+    // reached on; `None` defaults to the first one, mirroring
+    // `DeviceManager::tpm_info`. `None` is also returned when no TPM device
+    // matching `id` is configured at all, so the caller can tell "no such
+    // device" apart from "marked but `os-handoff-locking` wasn't enabled".
+    #[cfg(feature = "tpm")]
+    pub fn tpm_os_handoff(&self, id: Option<&str>) -> Option<()> {
+        let tpm = match id {
+            Some(id) => self.tpm_devices.iter().find(|(i, _)| i == id),
+            None => self.tpm_devices.first(),
+        }?;
+        tpm.1.lock().unwrap().os_handoff();
+        Some(())
+    }
+
+    // `id` selects which configured TPM device to wipe the permanent state
+    // of; `None` defaults to the first one, mirroring `DeviceManager::tpm_info`.
+    // `None` is also returned when no TPM device matching `id` is configured
+    // at all, so the caller can tell "no such device" apart from "reset but
+    // failed".
+    #[cfg(feature = "tpm")]
+    pub fn tpm_reset_state(&self, id: Option<&str>) -> Option<DeviceManagerResult<()>> {
+        let tpm = match id {
+            Some(id) => self.tpm_devices.iter().find(|(i, _)| i == id),
+            None => self.tpm_devices.first(),
+        }?;
+        Some(
+            tpm.1
+                .lock()
+                .unwrap()
+                .reset_state()
+                .map_err(DeviceManagerError::TpmResetState),
+        )
+    }
+
+    /// Location of the TPM measured-boot event log in guest memory, if a TPM
+    /// device was configured. Consumed by `acpi::create_acpi_tables` to fill
+    /// in the ACPI TPM2 table's LAML/LASA fields.
+    pub fn tpm_event_log_region(&self) -> Option<(GuestAddress, u64)> {
+        self.tpm_log_region
+    }
+
+    /// Dumps the current contents of the TPM measured-boot event log, for
+    /// the `vm.tpm-event-log` attestation debugging API.
+    pub fn tpm_event_log(&self) -> Option<DeviceManagerResult<Vec<u8>>> {
+        self.tpm_log_region.map(|(start, size)| {
+            let mut data = vec![0u8; size as usize];
+            self.memory_manager
+                .lock()
+                .unwrap()
+                .guest_memory()
+                .memory()
+                .read_slice(&mut data, start)
+                .map_err(DeviceManagerError::InitializeTpmLog)?;
+            Ok(data)
+        })
+    }
+
     pub fn resize_balloon(&mut self, size: u64) -> DeviceManagerResult<()> {
         if let Some(balloon) = &self.balloon {
             return balloon
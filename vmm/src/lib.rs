@@ -550,12 +550,14 @@ impl Vmm {
                 }
 
                 let device_tree = self.vm.as_ref().map(|vm| vm.device_tree());
+                let tpm = self.vm.as_ref().and_then(|vm| vm.tpm_info(None));
 
                 Ok(VmInfo {
                     config,
                     state,
                     memory_actual_size,
                     device_tree,
+                    tpm,
                 })
             }
             None => Err(VmError::VmNotCreated),
@@ -719,6 +721,71 @@ impl Vmm {
         }
     }
 
+    fn vm_tpm_info(&mut self, id: Option<String>) -> result::Result<Vec<u8>, VmError> {
+        if let Some(ref vm) = self.vm {
+            let info = vm.tpm_info(id.as_deref());
+            serde_json::to_vec(&info).map_err(VmError::SerializeJson)
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_tpm_event_log(&mut self) -> result::Result<Vec<u8>, VmError> {
+        if let Some(ref vm) = self.vm {
+            let log = vm.tpm_event_log().map_err(|e| {
+                error!("Error when getting the TPM event log from the VM: {:?}", e);
+                e
+            })?;
+            serde_json::to_vec(&log).map_err(VmError::SerializeJson)
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_tpm_reconnect(&mut self, id: Option<String>) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.tpm_reconnect(id.as_deref())
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_tpm_establishment(&mut self, id: Option<String>) -> result::Result<Vec<u8>, VmError> {
+        if let Some(ref vm) = self.vm {
+            let info = vm.tpm_establishment(id.as_deref());
+            serde_json::to_vec(&info).map_err(VmError::SerializeJson)
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_tpm_reset_established_flag(
+        &mut self,
+        id: Option<String>,
+    ) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.tpm_reset_established_flag(id.as_deref())
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_tpm_os_handoff(&mut self, id: Option<String>) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.tpm_os_handoff(id.as_deref())
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_tpm_reset_state(&mut self, id: Option<String>) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.tpm_reset_state(id.as_deref())
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
     fn vm_power_button(&mut self) -> result::Result<(), VmError> {
         if let Some(ref mut vm) = self.vm {
             vm.power_button()
@@ -1330,6 +1397,62 @@ impl Vmm {
 
                                     sender.send(response).map_err(Error::ApiResponseSend)?;
                                 }
+                                ApiRequest::VmTpmInfo(id, sender) => {
+                                    let response = self
+                                        .vm_tpm_info(id)
+                                        .map_err(ApiError::VmInfo)
+                                        .map(ApiResponsePayload::VmAction);
+
+                                    sender.send(response).map_err(Error::ApiResponseSend)?;
+                                }
+                                ApiRequest::VmTpmEventLog(sender) => {
+                                    let response = self
+                                        .vm_tpm_event_log()
+                                        .map_err(ApiError::VmInfo)
+                                        .map(ApiResponsePayload::VmAction);
+
+                                    sender.send(response).map_err(Error::ApiResponseSend)?;
+                                }
+                                ApiRequest::VmTpmReconnect(id, sender) => {
+                                    let response = self
+                                        .vm_tpm_reconnect(id)
+                                        .map_err(ApiError::VmTpmReconnect)
+                                        .map(|_| ApiResponsePayload::Empty);
+
+                                    sender.send(response).map_err(Error::ApiResponseSend)?;
+                                }
+                                ApiRequest::VmTpmEstablishment(id, sender) => {
+                                    let response = self
+                                        .vm_tpm_establishment(id)
+                                        .map_err(ApiError::VmInfo)
+                                        .map(ApiResponsePayload::VmAction);
+
+                                    sender.send(response).map_err(Error::ApiResponseSend)?;
+                                }
+                                ApiRequest::VmTpmResetEstablishedFlag(id, sender) => {
+                                    let response = self
+                                        .vm_tpm_reset_established_flag(id)
+                                        .map_err(ApiError::VmTpmResetEstablishedFlag)
+                                        .map(|_| ApiResponsePayload::Empty);
+
+                                    sender.send(response).map_err(Error::ApiResponseSend)?;
+                                }
+                                ApiRequest::VmTpmOsHandoff(id, sender) => {
+                                    let response = self
+                                        .vm_tpm_os_handoff(id)
+                                        .map_err(ApiError::VmTpmOsHandoff)
+                                        .map(|_| ApiResponsePayload::Empty);
+
+                                    sender.send(response).map_err(Error::ApiResponseSend)?;
+                                }
+                                ApiRequest::VmTpmResetState(id, sender) => {
+                                    let response = self
+                                        .vm_tpm_reset_state(id)
+                                        .map_err(ApiError::VmTpmResetState)
+                                        .map(|_| ApiResponsePayload::Empty);
+
+                                    sender.send(response).map_err(Error::ApiResponseSend)?;
+                                }
                                 ApiRequest::VmReceiveMigration(receive_migration_data, sender) => {
                                     let response = self
                                         .vm_receive_migration(
@@ -154,6 +154,12 @@ pub enum Error {
     /// VM is not running
     VmNotRunning,
 
+    /// No TPM device matches the requested id (or none is configured at all)
+    TpmDeviceNotFound,
+
+    /// `vm.tpm-reset-state` was called while the VM was running
+    TpmResetStateRequiresPausedOrShutOff,
+
     /// Cannot clone EventFd.
     EventFdClone(io::Error),
 
@@ -1131,6 +1137,12 @@ impl Vm {
             .resume()
             .map_err(Error::Resume)?;
 
+        // A reboot tears down and recreates the VM, but the TPM backend
+        // process (e.g. swtpm) is not restarted along with it, so its
+        // in-flight command/response state needs to be cleared explicitly.
+        #[cfg(feature = "tpm")]
+        self.device_manager.lock().unwrap().reset_tpm();
+
         self.cpu_manager
             .lock()
             .unwrap()
@@ -1520,6 +1532,162 @@ impl Vm {
         Ok(self.device_manager.lock().unwrap().counters())
     }
 
+    #[cfg(feature = "tpm")]
+    pub fn tpm_info(&self, id: Option<&str>) -> Option<crate::api::VmTpmInfo> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .tpm_info(id)
+            .map(|info| crate::api::VmTpmInfo {
+                backend: info.backend,
+                backend_healthy: info.backend_healthy,
+                state_encrypted: info.state_encrypted,
+                cmd_buffer_size: info.cmd_buffer_size,
+                resp_buffer_size: info.resp_buffer_size,
+                active_locality: info.active_locality,
+                established_flag: info.established_flag,
+                localities: info
+                    .localities
+                    .into_iter()
+                    .map(|l| crate::api::VmTpmLocalityInfo {
+                        access: l.access,
+                        sts: l.sts,
+                        inte: l.inte,
+                        ints: l.ints,
+                        int_vector: l.int_vector,
+                    })
+                    .collect(),
+                pcr_banks: info
+                    .pcr_banks
+                    .into_iter()
+                    .map(|b| crate::api::VmPcrBankInfo {
+                        algorithm_id: b.algorithm_id,
+                        algorithm_name: b.algorithm_name.to_owned(),
+                        pcr_select: b.pcr_select,
+                    })
+                    .collect(),
+                self_test_done: info.self_test_done,
+                backend_version: info.backend_version,
+                backend_build_info: info.backend_build_info,
+            })
+    }
+
+    /// No TPM support was compiled in (the `tpm` cargo feature is off).
+    #[cfg(not(feature = "tpm"))]
+    pub fn tpm_info(&self, _id: Option<&str>) -> Option<crate::api::VmTpmInfo> {
+        None
+    }
+
+    /// Dials a TPM backend that hasn't connected yet (see `defer-connect`),
+    /// for the `vm.tpm-reconnect` API.
+    #[cfg(feature = "tpm")]
+    pub fn tpm_reconnect(&self, id: Option<&str>) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .tpm_reconnect(id)
+            .ok_or(Error::TpmDeviceNotFound)?
+            .map_err(Error::DeviceManager)
+    }
+
+    /// No TPM support was compiled in (the `tpm` cargo feature is off).
+    #[cfg(not(feature = "tpm"))]
+    pub fn tpm_reconnect(&self, _id: Option<&str>) -> Result<()> {
+        Err(Error::TpmDeviceNotFound)
+    }
+
+    /// Queries a TPM device's establishment flag, for the
+    /// `vm.tpm-establishment` API.
+    #[cfg(feature = "tpm")]
+    pub fn tpm_establishment(&self, id: Option<&str>) -> Option<crate::api::VmTpmEstablishmentInfo> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .tpm_info(id)
+            .map(|info| crate::api::VmTpmEstablishmentInfo {
+                established: info.established_flag,
+            })
+    }
+
+    /// No TPM support was compiled in (the `tpm` cargo feature is off).
+    #[cfg(not(feature = "tpm"))]
+    pub fn tpm_establishment(&self, _id: Option<&str>) -> Option<crate::api::VmTpmEstablishmentInfo> {
+        None
+    }
+
+    /// Resets a TPM device's establishment flag out of band, for the
+    /// `vm.tpm-establishment` API.
+    #[cfg(feature = "tpm")]
+    pub fn tpm_reset_established_flag(&self, id: Option<&str>) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .tpm_reset_established_flag(id)
+            .ok_or(Error::TpmDeviceNotFound)?
+            .map_err(Error::DeviceManager)
+    }
+
+    /// No TPM support was compiled in (the `tpm` cargo feature is off).
+    #[cfg(not(feature = "tpm"))]
+    pub fn tpm_reset_established_flag(&self, _id: Option<&str>) -> Result<()> {
+        Err(Error::TpmDeviceNotFound)
+    }
+
+    /// Wipes a TPM backend's permanent state and reinitializes it, for the
+    /// `vm.tpm-reset-state` API: lets a VM definition be re-enrolled for
+    /// attestation without manually deleting the backend's state directory.
+    /// Refused while the VM is running, since a guest mid-boot or further
+    /// along has no reason to expect its TPM to vanish out from under it.
+    #[cfg(feature = "tpm")]
+    pub fn tpm_reset_state(&self, id: Option<&str>) -> Result<()> {
+        let current_state = self.get_state()?;
+        if current_state != VmState::Paused && current_state != VmState::Shutdown {
+            return Err(Error::TpmResetStateRequiresPausedOrShutOff);
+        }
+
+        self.device_manager
+            .lock()
+            .unwrap()
+            .tpm_reset_state(id)
+            .ok_or(Error::TpmDeviceNotFound)?
+            .map_err(Error::DeviceManager)
+    }
+
+    /// No TPM support was compiled in (the `tpm` cargo feature is off).
+    #[cfg(not(feature = "tpm"))]
+    pub fn tpm_reset_state(&self, _id: Option<&str>) -> Result<()> {
+        Err(Error::TpmDeviceNotFound)
+    }
+
+    /// Marks a TPM device's OS handoff point reached out of band, for the
+    /// `vm.tpm-os-handoff` API. A no-op on the device unless it was
+    /// configured with `os-handoff-locking=on`.
+    #[cfg(feature = "tpm")]
+    pub fn tpm_os_handoff(&self, id: Option<&str>) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .tpm_os_handoff(id)
+            .ok_or(Error::TpmDeviceNotFound)
+    }
+
+    /// No TPM support was compiled in (the `tpm` cargo feature is off).
+    #[cfg(not(feature = "tpm"))]
+    pub fn tpm_os_handoff(&self, _id: Option<&str>) -> Result<()> {
+        Err(Error::TpmDeviceNotFound)
+    }
+
+    /// Dumps the TPM measured-boot event log for the `vm.tpm-event-log`
+    /// attestation debugging API. `Ok(None)` means no TPM is configured.
+    pub fn tpm_event_log(&self) -> Result<Option<Vec<u8>>> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .tpm_event_log()
+            .transpose()
+            .map_err(Error::DeviceManager)
+    }
+
     fn os_signal_handler(
         mut signals: Signals,
         console_input_clone: Arc<Console>,
@@ -2532,17 +2700,23 @@ mod tests {
                 MmioDeviceInfo {
                     addr: 0x00,
                     irq: 33,
+                    length: LEN,
                 },
             ),
             (
                 (DeviceType::Virtio(1), "virtio".to_string()),
-                MmioDeviceInfo { addr: LEN, irq: 34 },
+                MmioDeviceInfo {
+                    addr: LEN,
+                    irq: 34,
+                    length: LEN,
+                },
             ),
             (
                 (DeviceType::Rtc, "rtc".to_string()),
                 MmioDeviceInfo {
                     addr: 2 * LEN,
                     irq: 35,
+                    length: LEN,
                 },
             ),
         ]
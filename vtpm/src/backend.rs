@@ -0,0 +1,244 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::ptm::{Capabilities, PtmGetConfig, PtmGetInfo, PtmInit, PtmSetBufferSize, StateBlobType};
+use crate::Result;
+
+/// Abstraction over a TPM implementation that can execute TPM commands and
+/// answer the handful of out-of-band queries (establishment flag, locality,
+/// cancellation) that the TIS/CRB front-ends need regardless of how the
+/// actual TPM is implemented (an external `swtpm` process, an in-process
+/// simulator, ...).
+///
+/// Methods take `&mut self`: callers are expected to hold the implementation
+/// behind an `Arc<Mutex<dyn TpmBackend>>` (see `devices::legacy::tpm::new`)
+/// rather than relying on interior mutability here, so the `Send` bound
+/// alone is enough for `Mutex` to make the handle shareable between the
+/// vCPU thread and any worker thread driving the backend's I/O.
+pub trait TpmBackend: Send {
+    /// Perform whatever handshake is necessary before commands can be sent
+    /// (e.g. the swtpm `CMD_INIT` control command). `init.init_flags` lets
+    /// the caller request [`crate::ptm::PTM_INIT_FLAG_DELETE_VOLATILE`] to
+    /// discard any state stashed by a prior [`TpmBackend::store_volatile`]
+    /// instead of reloading it.
+    fn startup(&mut self, init: PtmInit) -> Result<()>;
+
+    /// Ask the backend to persist its current volatile state (the parts of
+    /// TPM state that would normally be lost on power-off) so it survives
+    /// until the next [`TpmBackend::startup`]. Called when pausing the VM.
+    fn store_volatile(&mut self) -> Result<()>;
+
+    /// Send a raw TPM command blob and return the raw TPM response blob.
+    /// Takes `cmd` by reference rather than by value so callers (e.g. the
+    /// TIS front-end) can hand over a command buffer without cloning it
+    /// first; only the response allocates.
+    fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>>;
+
+    /// Ask the backend to abort whatever command is currently executing.
+    fn cancel_cmd(&mut self) -> Result<()>;
+
+    /// Query the TPM establishment flag for the current locality.
+    fn get_established_flag(&mut self) -> Result<bool>;
+
+    /// Reset the TPM establishment flag; only meaningful from localities 3
+    /// and 4.
+    fn reset_established_flag(&mut self, locality: u8) -> Result<()>;
+
+    /// Notify the backend that the active locality changed.
+    fn set_locality(&mut self, locality: u8) -> Result<()>;
+
+    /// Negotiate the data channel buffer size: `requested` of `0` just
+    /// queries the current size. Returns the size actually in effect along
+    /// with the backend's supported range, so callers can clamp future
+    /// requests without round-tripping again.
+    fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize>;
+
+    /// Begin a locality 4 pre-boot hash sequence (H-CRTM), as used by
+    /// firmware to extend PCRs before the main TPM command interface is
+    /// available.
+    fn hash_start(&mut self) -> Result<()>;
+
+    /// Feed a chunk of data into the in-progress hash sequence.
+    fn hash_data(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Finish the hash sequence and extend the corresponding PCR.
+    fn hash_end(&mut self) -> Result<()>;
+
+    /// Cleanly stop the backend.
+    fn shutdown(&mut self) -> Result<()>;
+
+    /// Pause the backend in place, ready to resume with another
+    /// [`TpmBackend::startup`], without tearing it down for good the way
+    /// [`TpmBackend::shutdown`] does. Callers that already have the backend
+    /// running (e.g. [`TpmTisCore::resume`](crate) after a VM pause, or a
+    /// guest-triggered reset) must call this before calling `startup` again;
+    /// sending a second `TPM2_Startup`-equivalent handshake to a backend
+    /// that never stopped is what confuses real `swtpm`. The default
+    /// implementation is a no-op, which is correct for a backend (like
+    /// [`crate::TpmSimulator`]) with no live-vs-stopped distinction of its
+    /// own to make; [`crate::TpmEmulator`] overrides this to send `CmdStop`.
+    fn stop(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Query the flags describing how the backend was started (e.g.
+    /// whether its persisted state is encrypted at rest).
+    fn get_config(&mut self) -> Result<PtmGetConfig>;
+
+    /// Fetch one of the backend's persisted state blobs, for inclusion in a
+    /// VM snapshot. `passphrase` must be supplied when the backend's state
+    /// is encrypted at rest (see [`PtmGetConfig`]); pass `None` otherwise.
+    fn get_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Vec<u8>>;
+
+    /// Restore one of the backend's persisted state blobs, e.g. from a VM
+    /// snapshot. `passphrase` must match whatever the blob was encrypted
+    /// with; pass `None` for a blob that isn't encrypted.
+    fn set_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        data: &[u8],
+        passphrase: Option<&[u8]>,
+    ) -> Result<()>;
+
+    /// Which control channel commands this backend implements, so a caller
+    /// can refuse a configuration the backend could never honor (e.g.
+    /// encrypted snapshots without state blob support) with a clear error
+    /// instead of failing deep inside a later command. The default
+    /// implementation reports every capability as supported, which is
+    /// correct for a backend (like [`crate::TpmSimulator`]) that implements
+    /// everything behaviorally rather than through the PTM control channel
+    /// [`crate::ptm::Capabilities`] actually describes; backends that can
+    /// only do a subset, or that need to probe the other end to find out,
+    /// override this.
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        Ok(Capabilities::all())
+    }
+
+    /// Make sure the backend is actually reachable, dialing it if it isn't
+    /// yet. Every other method above may assume this has already succeeded
+    /// (and the default implementation is a no-op for exactly that reason);
+    /// only a backend that can be constructed without being reachable yet
+    /// (e.g. [`crate::TpmEmulator`] configured to defer connecting) needs to
+    /// override it with real dialing logic.
+    fn ensure_connected(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the backend is currently reachable, without attempting to
+    /// connect it. The default of `true` is correct for any backend that's
+    /// always reachable once constructed; only a backend that can be
+    /// disconnected (or never connected in the first place) overrides this.
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    /// Query the backend's version and build information, for `vm.tpm-info`
+    /// and startup logs: a bug report naming the exact emulator build a VM
+    /// used is worth far more than "some swtpm". The default reports no
+    /// version information, which is correct for a backend (like
+    /// [`crate::TpmSimulator`]) with no separate version of its own to
+    /// report; [`crate::TpmEmulator`] overrides this to ask swtpm.
+    fn get_info(&mut self) -> Result<PtmGetInfo> {
+        Ok(PtmGetInfo::default())
+    }
+}
+
+/// Lets a boxed trait object be used wherever a `TpmBackend` is expected
+/// (e.g. as the sized value a wrapper like [`crate::policy::DenyListBackend`]
+/// holds onto and itself implements `TpmBackend` for), rather than forcing
+/// every wrapper to be generic over the concrete backend type it wraps.
+impl TpmBackend for Box<dyn TpmBackend> {
+    fn startup(&mut self, init: PtmInit) -> Result<()> {
+        (**self).startup(init)
+    }
+
+    fn store_volatile(&mut self) -> Result<()> {
+        (**self).store_volatile()
+    }
+
+    fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+        (**self).deliver_request(cmd)
+    }
+
+    fn cancel_cmd(&mut self) -> Result<()> {
+        (**self).cancel_cmd()
+    }
+
+    fn get_established_flag(&mut self) -> Result<bool> {
+        (**self).get_established_flag()
+    }
+
+    fn reset_established_flag(&mut self, locality: u8) -> Result<()> {
+        (**self).reset_established_flag(locality)
+    }
+
+    fn set_locality(&mut self, locality: u8) -> Result<()> {
+        (**self).set_locality(locality)
+    }
+
+    fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize> {
+        (**self).set_buffer_size(requested)
+    }
+
+    fn hash_start(&mut self) -> Result<()> {
+        (**self).hash_start()
+    }
+
+    fn hash_data(&mut self, data: &[u8]) -> Result<()> {
+        (**self).hash_data(data)
+    }
+
+    fn hash_end(&mut self) -> Result<()> {
+        (**self).hash_end()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        (**self).shutdown()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        (**self).stop()
+    }
+
+    fn get_config(&mut self) -> Result<PtmGetConfig> {
+        (**self).get_config()
+    }
+
+    fn get_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        (**self).get_state_blob(blob_type, passphrase)
+    }
+
+    fn set_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        data: &[u8],
+        passphrase: Option<&[u8]>,
+    ) -> Result<()> {
+        (**self).set_state_blob(blob_type, data, passphrase)
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        (**self).capabilities()
+    }
+
+    fn ensure_connected(&mut self) -> Result<()> {
+        (**self).ensure_connected()
+    }
+
+    fn is_connected(&self) -> bool {
+        (**self).is_connected()
+    }
+
+    fn get_info(&mut self) -> Result<PtmGetInfo> {
+        (**self).get_info()
+    }
+}
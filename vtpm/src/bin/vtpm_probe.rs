@@ -0,0 +1,108 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Standalone bring-up check for an `swtpm` instance: connects to its
+//! control/data channel sockets, runs through the same handshake
+//! `TpmEmulator` would on a real VM boot (`CmdInit`, a capability probe, a
+//! buffer size negotiation), then issues `TPM2_GetRandom` to confirm the
+//! TPM command interface itself is actually answering, not just the control
+//! channel. Meant to be run by hand against a manually started swtpm before
+//! pointing a VM at it.
+
+use std::process;
+
+use clap::{crate_authors, App, Arg};
+use thiserror::Error;
+use vtpm::ptm::PtmInit;
+use vtpm::{ReconnectPolicy, TpmBackend, TpmEmulator};
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("failed to connect to swtpm: {0}")]
+    Connect(#[source] vtpm::Error),
+    #[error("CmdInit failed: {0}")]
+    Init(#[source] vtpm::Error),
+    #[error("capability probe failed: {0}")]
+    Capabilities(#[source] vtpm::Error),
+    #[error("buffer size negotiation failed: {0}")]
+    BufferSize(#[source] vtpm::Error),
+    #[error("TPM2_GetRandom failed: {0}")]
+    GetRandom(#[source] vtpm::Error),
+}
+
+fn probe(ctrl_path: &str, data_path: &str, buffer_size: u32) -> Result<(), Error> {
+    println!("connecting to ctrl={} data={}", ctrl_path, data_path);
+    let mut emulator =
+        TpmEmulator::new(ctrl_path, data_path, ReconnectPolicy::default()).map_err(Error::Connect)?;
+    println!("connected");
+
+    emulator.startup(PtmInit::default()).map_err(Error::Init)?;
+    println!("CmdInit succeeded");
+
+    let caps = emulator.capabilities().map_err(Error::Capabilities)?;
+    println!(
+        "capabilities: cancel={} stateblob={} get_config={} buffer_size={}",
+        caps.supports_cancel(),
+        caps.supports_stateblob(),
+        caps.supports_get_config(),
+        caps.supports_buffer_size(),
+    );
+
+    let negotiated = emulator
+        .set_buffer_size(buffer_size)
+        .map_err(Error::BufferSize)?;
+    println!(
+        "negotiated buffer size: {} (range {}..={})",
+        negotiated.buffersize, negotiated.minsize, negotiated.maxsize
+    );
+
+    let random = vtpm::get_random(&mut emulator, 16).map_err(Error::GetRandom)?;
+    println!("TPM2_GetRandom returned {} bytes: {:02x?}", random.len(), random);
+
+    Ok(())
+}
+
+fn main() {
+    let matches = App::new("vtpm-probe")
+        .author(crate_authors!())
+        .about("Verify an swtpm instance is reachable and answering TPM commands")
+        .arg(
+            Arg::with_name("ctrl-path")
+                .long("ctrl-path")
+                .help("Path to swtpm's control channel UNIX domain socket")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("data-path")
+                .long("data-path")
+                .help("Path to swtpm's data channel UNIX domain socket")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("buffer-size")
+                .long("buffer-size")
+                .help("Data channel buffer size to request, in bytes")
+                .takes_value(true)
+                .default_value("4096"),
+        )
+        .get_matches();
+
+    let ctrl_path = matches.value_of("ctrl-path").unwrap();
+    let data_path = matches.value_of("data-path").unwrap();
+    let buffer_size: u32 = match matches.value_of("buffer-size").unwrap().parse() {
+        Ok(size) => size,
+        Err(e) => {
+            eprintln!("Error parsing --buffer-size: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = probe(ctrl_path, data_path, buffer_size) {
+        eprintln!("Error probing swtpm: {}", e);
+        process::exit(1);
+    }
+}
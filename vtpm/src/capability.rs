@@ -0,0 +1,263 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! `TPM2_GetCapability` helper for discovering the PCR banks (hash
+//! algorithms) a backend supports.
+//!
+//! This is deliberately separate from [`crate::ptm`]: `ptm` speaks the
+//! swtpm control channel, which knows nothing about TPM 2.0 command
+//! semantics, while this module builds and parses an actual TPM2 command
+//! sent through [`crate::TpmBackend::deliver_request`] like any guest
+//! command would be. Only the handful of fields needed to answer "which PCR
+//! banks exist" are parsed; a full `TPM2_GetCapability` response can carry
+//! other capability shapes this module does not need to understand.
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_CC_GET_CAPABILITY: u32 = 0x0000_017a;
+const TPM_CAP_PCRS: u32 = 0x0000_0005;
+const TPM_RC_SUCCESS: u32 = 0;
+
+/// One PCR bank reported by `TPM2_GetCapability(TPM_CAP_PCRS)`: a hash
+/// algorithm and the set of PCRs implemented under it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcrBank {
+    /// The `TPM_ALG_ID` this bank is extended with (e.g. `0x000b` for
+    /// SHA-256).
+    pub algorithm_id: u16,
+    /// Human readable name for [`PcrBank::algorithm_id`], or `"unknown"` for
+    /// an algorithm this module doesn't have a name for.
+    pub algorithm_name: &'static str,
+    /// Bitmap of implemented PCRs under this bank, one bit per PCR index,
+    /// as returned on the wire (`TPMS_PCR_SELECT::pcrSelect`).
+    pub pcr_select: Vec<u8>,
+}
+
+fn algorithm_name(id: u16) -> &'static str {
+    match id {
+        0x0004 => "SHA1",
+        0x000b => "SHA256",
+        0x000c => "SHA384",
+        0x000d => "SHA512",
+        0x0012 => "SM3_256",
+        _ => "unknown",
+    }
+}
+
+/// Builds the fixed-size `TPM2_GetCapability(TPM_CAP_PCRS, property: 0,
+/// propertyCount: 1)` command. The command has no variable-length fields, so
+/// its encoding is always exactly this many bytes.
+fn get_capability_pcrs_command() -> Vec<u8> {
+    let mut cmd = Vec::with_capacity(22);
+    cmd.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+    cmd.extend_from_slice(&22u32.to_be_bytes()); // commandSize
+    cmd.extend_from_slice(&TPM_CC_GET_CAPABILITY.to_be_bytes());
+    cmd.extend_from_slice(&TPM_CAP_PCRS.to_be_bytes()); // capability
+    cmd.extend_from_slice(&0u32.to_be_bytes()); // property
+    cmd.extend_from_slice(&1u32.to_be_bytes()); // propertyCount
+    cmd
+}
+
+/// Parses a `TPM2_GetCapability(TPM_CAP_PCRS)` response's
+/// `TPML_PCR_SELECTION` body (the `moreData` byte and `TPM_CAP` tag are
+/// already consumed by the caller) into the list of PCR banks it describes.
+fn parse_pcr_selection(body: &[u8]) -> Result<Vec<PcrBank>> {
+    let count = u32::from_be_bytes(
+        body.get(0..4)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    ) as usize;
+
+    let mut banks = Vec::with_capacity(count);
+    let mut offset = 4;
+    for _ in 0..count {
+        let hash = u16::from_be_bytes(
+            body.get(offset..offset + 2)
+                .ok_or(Error::MalformedResponse)?
+                .try_into()
+                .unwrap(),
+        );
+        let size_of_select = *body.get(offset + 2).ok_or(Error::MalformedResponse)? as usize;
+        let select_start = offset + 3;
+        let select_end = select_start + size_of_select;
+        let pcr_select = body
+            .get(select_start..select_end)
+            .ok_or(Error::MalformedResponse)?
+            .to_vec();
+        banks.push(PcrBank {
+            algorithm_id: hash,
+            algorithm_name: algorithm_name(hash),
+            pcr_select,
+        });
+        offset = select_end;
+    }
+    Ok(banks)
+}
+
+/// Issues `TPM2_GetCapability(TPM_CAP_PCRS)` through `backend` and returns
+/// the PCR banks it reports. Intended to be called once, e.g. at TIS device
+/// construction time, so the result can be cached and surfaced through
+/// `vm.tpm-info` without round-tripping to the backend on every query.
+pub fn query_pcr_banks(backend: &mut dyn TpmBackend) -> Result<Vec<PcrBank>> {
+    let response = backend.deliver_request(&get_capability_pcrs_command())?;
+
+    // Header: tag (2) + responseSize (4) + responseCode (4).
+    let response_code = u32::from_be_bytes(
+        response
+            .get(6..10)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    );
+    if response_code != TPM_RC_SUCCESS {
+        return Err(Error::TpmCommandFailed(response_code));
+    }
+
+    // moreData (1 byte) + capability tag (4 bytes) precede the
+    // TPML_PCR_SELECTION body we actually care about.
+    let body = response.get(15..).ok_or(Error::MalformedResponse)?;
+    parse_pcr_selection(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn success_response(pcr_selections: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(pcr_selections.len() as u32).to_be_bytes());
+        for (hash, select) in pcr_selections {
+            body.extend_from_slice(&hash.to_be_bytes());
+            body.push(select.len() as u8);
+            body.extend_from_slice(select);
+        }
+
+        let mut response = Vec::new();
+        response.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        response.extend_from_slice(&0u32.to_be_bytes()); // responseSize, unused by the parser
+        response.extend_from_slice(&TPM_RC_SUCCESS.to_be_bytes());
+        response.push(0); // moreData
+        response.extend_from_slice(&TPM_CAP_PCRS.to_be_bytes());
+        response.extend_from_slice(&body);
+        response
+    }
+
+    struct StubBackend {
+        response: Vec<u8>,
+    }
+
+    impl TpmBackend for StubBackend {
+        fn startup(&mut self, _init: crate::ptm::PtmInit) -> Result<()> {
+            Ok(())
+        }
+
+        fn store_volatile(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn deliver_request(&mut self, _cmd: &[u8]) -> Result<Vec<u8>> {
+            Ok(self.response.clone())
+        }
+
+        fn cancel_cmd(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_established_flag(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_buffer_size(&mut self, requested: u32) -> Result<crate::ptm::PtmSetBufferSize> {
+            Ok(crate::ptm::PtmSetBufferSize {
+                buffersize: requested,
+                minsize: requested,
+                maxsize: requested,
+            })
+        }
+
+        fn hash_start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_end(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_config(&mut self) -> Result<crate::ptm::PtmGetConfig> {
+            Ok(crate::ptm::PtmGetConfig { flags: 0 })
+        }
+
+        fn get_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _passphrase: Option<&[u8]>,
+        ) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn set_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _data: &[u8],
+            _passphrase: Option<&[u8]>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_query_pcr_banks_parses_sha1_and_sha256() {
+        let mut backend = StubBackend {
+            response: success_response(&[(0x0004, &[0xff, 0xff, 0xff]), (0x000b, &[0xff, 0xff, 0xff])]),
+        };
+        let banks = query_pcr_banks(&mut backend).unwrap();
+        assert_eq!(banks.len(), 2);
+        assert_eq!(banks[0].algorithm_id, 0x0004);
+        assert_eq!(banks[0].algorithm_name, "SHA1");
+        assert_eq!(banks[1].algorithm_id, 0x000b);
+        assert_eq!(banks[1].algorithm_name, "SHA256");
+        assert_eq!(banks[1].pcr_select, vec![0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_query_pcr_banks_reports_tpm_error_response_code() {
+        let mut response = Vec::new();
+        response.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        response.extend_from_slice(&10u32.to_be_bytes());
+        response.extend_from_slice(&0x0000_0101u32.to_be_bytes()); // some non-zero TPM_RC
+        let mut backend = StubBackend { response };
+        let err = query_pcr_banks(&mut backend).unwrap_err();
+        assert!(matches!(err, Error::TpmCommandFailed(0x0000_0101)));
+    }
+
+    #[test]
+    fn test_query_pcr_banks_rejects_truncated_response() {
+        let mut backend = StubBackend {
+            response: vec![0u8; 5],
+        };
+        assert!(matches!(
+            query_pcr_banks(&mut backend).unwrap_err(),
+            Error::MalformedResponse
+        ));
+    }
+}
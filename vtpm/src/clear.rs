@@ -0,0 +1,271 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persisting a deferred `TPM2_Clear` across VMM restarts.
+//!
+//! [`policy::DenyListBackend`](crate::policy::DenyListBackend) can be
+//! configured to refuse `TPM2_Clear` outright, but a host that denies it
+//! live may still want the guest's request honored the next time the VM
+//! boots (e.g. "no clearing a running TPM, but a reboot may still reset
+//! it"). This module lets that intent survive a VMM restart by writing a
+//! marker file next to the backend's own host-side state, and applies it by
+//! sending the real `TPM2_Clear` command the same way any other caller would
+//! through [`TpmBackend::deliver_request`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+
+const TPM_ST_SESSIONS: u16 = 0x8002;
+pub(crate) const TPM_CC_CLEAR: u32 = 0x0000_0126;
+const TPM_RH_PLATFORM: u32 = 0x4000_000c;
+const TPM_RS_PW: u32 = 0x4000_0009;
+const TPM_RC_SUCCESS: u32 = 0;
+
+/// Name of the marker file [`request_clear`]/[`apply_pending_clear`] look
+/// for, relative to a backend's state directory.
+const MARKER_FILE_NAME: &str = "pending-clear";
+
+/// Builds the `TPM2_Clear(TPM_RH_PLATFORM)` command, authorized with the
+/// empty-password session swtpm's simulator accepts for the platform
+/// hierarchy out of the box.
+fn clear_command() -> Vec<u8> {
+    let mut auth_area = Vec::with_capacity(9);
+    auth_area.extend_from_slice(&TPM_RS_PW.to_be_bytes());
+    auth_area.extend_from_slice(&0u16.to_be_bytes()); // nonce size
+    auth_area.push(0); // session attributes
+    auth_area.extend_from_slice(&0u16.to_be_bytes()); // hmac size
+
+    let command_size = 2 + 4 + 4 + 4 + 4 + auth_area.len() as u32;
+    let mut cmd = Vec::with_capacity(command_size as usize);
+    cmd.extend_from_slice(&TPM_ST_SESSIONS.to_be_bytes());
+    cmd.extend_from_slice(&command_size.to_be_bytes());
+    cmd.extend_from_slice(&TPM_CC_CLEAR.to_be_bytes());
+    cmd.extend_from_slice(&TPM_RH_PLATFORM.to_be_bytes());
+    cmd.extend_from_slice(&(auth_area.len() as u32).to_be_bytes());
+    cmd.extend_from_slice(&auth_area);
+    cmd
+}
+
+/// Path of the pending-clear marker for a backend whose host-side state
+/// lives under `state_dir`.
+pub fn marker_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(MARKER_FILE_NAME)
+}
+
+/// Records that the backend behind `marker` should be cleared the next time
+/// it starts up. Idempotent: requesting a clear that is already pending just
+/// leaves the marker in place.
+pub fn request_clear(marker: &Path) -> std::io::Result<()> {
+    fs::write(marker, [])
+}
+
+/// Sends `TPM2_Clear(TPM_RH_PLATFORM)` to `backend` right now and checks the
+/// response. Wipes the owner, endorsement and lockout hierarchies along with
+/// most NV indices, handing the TPM back in its factory-default-authorization
+/// state; it does not itself re-run the startup handshake.
+pub fn send_clear(backend: &mut dyn TpmBackend) -> Result<()> {
+    let response = backend.deliver_request(&clear_command())?;
+    let response_code = u32::from_be_bytes(
+        response
+            .get(6..10)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    );
+    if response_code != TPM_RC_SUCCESS {
+        return Err(Error::TpmCommandFailed(response_code));
+    }
+    Ok(())
+}
+
+/// If a clear was requested via [`request_clear`] for `marker`, sends
+/// `TPM2_Clear` to `backend` and removes the marker. Meant to be called once,
+/// right after [`TpmBackend::startup`], so the reset happens before guest
+/// firmware gets a chance to run and measure anything into the TPM it is
+/// about to lose.
+pub fn apply_pending_clear(backend: &mut dyn TpmBackend, marker: &Path) -> Result<()> {
+    if !marker.exists() {
+        return Ok(());
+    }
+
+    send_clear(backend)?;
+
+    // Best-effort: if removal fails the next startup will just clear again,
+    // which is harmless.
+    let _ = fs::remove_file(marker);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    struct RecordingBackend {
+        last_command_code: AtomicU32,
+        response_code: u32,
+    }
+
+    impl TpmBackend for RecordingBackend {
+        fn startup(&mut self, _init: crate::ptm::PtmInit) -> Result<()> {
+            Ok(())
+        }
+
+        fn store_volatile(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+            let command_code = u32::from_be_bytes(cmd[6..10].try_into().unwrap());
+            self.last_command_code.store(command_code, Ordering::SeqCst);
+
+            let mut response = Vec::new();
+            response.extend_from_slice(&0x8001u16.to_be_bytes());
+            response.extend_from_slice(&10u32.to_be_bytes());
+            response.extend_from_slice(&self.response_code.to_be_bytes());
+            Ok(response)
+        }
+
+        fn cancel_cmd(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_established_flag(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_buffer_size(
+            &mut self,
+            requested: u32,
+        ) -> Result<crate::ptm::PtmSetBufferSize> {
+            Ok(crate::ptm::PtmSetBufferSize {
+                buffersize: requested,
+                minsize: requested,
+                maxsize: requested,
+            })
+        }
+
+        fn hash_start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_end(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_config(&mut self) -> Result<crate::ptm::PtmGetConfig> {
+            Ok(crate::ptm::PtmGetConfig { flags: 0 })
+        }
+
+        fn get_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _passphrase: Option<&[u8]>,
+        ) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn set_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _data: &[u8],
+            _passphrase: Option<&[u8]>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_send_clear_sends_tpm2_clear_and_reports_failure() {
+        let mut backend = RecordingBackend {
+            last_command_code: AtomicU32::new(0),
+            response_code: TPM_RC_SUCCESS,
+        };
+        send_clear(&mut backend).unwrap();
+        assert_eq!(backend.last_command_code.load(Ordering::SeqCst), TPM_CC_CLEAR);
+
+        let mut failing_backend = RecordingBackend {
+            last_command_code: AtomicU32::new(0),
+            response_code: 0x0144,
+        };
+        assert!(send_clear(&mut failing_backend).is_err());
+    }
+
+    #[test]
+    fn test_apply_pending_clear_is_a_no_op_without_a_marker() {
+        let tmp_dir = std::env::temp_dir().join("ch-tpm-clear-test-no-marker");
+        let marker = marker_path(&tmp_dir);
+        let mut backend = RecordingBackend {
+            last_command_code: AtomicU32::new(0),
+            response_code: TPM_RC_SUCCESS,
+        };
+
+        apply_pending_clear(&mut backend, &marker).unwrap();
+
+        assert_eq!(backend.last_command_code.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_apply_pending_clear_sends_tpm2_clear_and_removes_the_marker() {
+        let tmp_dir = std::env::temp_dir().join(format!(
+            "ch-tpm-clear-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let marker = marker_path(&tmp_dir);
+        request_clear(&marker).unwrap();
+
+        let mut backend = RecordingBackend {
+            last_command_code: AtomicU32::new(0),
+            response_code: TPM_RC_SUCCESS,
+        };
+
+        apply_pending_clear(&mut backend, &marker).unwrap();
+
+        assert_eq!(backend.last_command_code.load(Ordering::SeqCst), TPM_CC_CLEAR);
+        assert!(!marker.exists());
+
+        let _ = fs::remove_dir_all(&tmp_dir);
+    }
+
+    #[test]
+    fn test_apply_pending_clear_keeps_the_marker_on_failure() {
+        let tmp_dir = std::env::temp_dir().join(format!(
+            "ch-tpm-clear-test-failure-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let marker = marker_path(&tmp_dir);
+        request_clear(&marker).unwrap();
+
+        let mut backend = RecordingBackend {
+            last_command_code: AtomicU32::new(0),
+            response_code: 0x0144, // some non-zero TPM_RC
+        };
+
+        assert!(apply_pending_clear(&mut backend, &marker).is_err());
+        assert!(marker.exists(), "a failed clear should stay pending");
+
+        let _ = fs::remove_dir_all(&tmp_dir);
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Provisioning an endorsement key (EK) certificate into the vTPM's NV
+//! storage, so guest attestation flows that expect to read one back (e.g.
+//! from the standard RSA EK certificate NV index) find it already populated
+//! at boot.
+//!
+//! Like [`crate::clear`], this speaks real `TPM2_NV_DefineSpace`/
+//! `TPM2_NV_Write` commands through [`TpmBackend::deliver_request`],
+//! authorized with the platform hierarchy's empty-password session the same
+//! way [`crate::clear::apply_pending_clear`] authorizes `TPM2_Clear`.
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+
+const TPM_ST_SESSIONS: u16 = 0x8002;
+const TPM_CC_NV_DEFINE_SPACE: u32 = 0x0000_012a;
+const TPM_CC_NV_WRITE: u32 = 0x0000_0137;
+const TPM_RH_PLATFORM: u32 = 0x4000_000c;
+const TPM_RS_PW: u32 = 0x4000_0009;
+const TPM_RC_SUCCESS: u32 = 0;
+const TPM_RC_NV_DEFINED: u32 = 0x0000_014c;
+const TPM_ALG_SHA256: u16 = 0x000b;
+
+/// Standard NV index for the RSA endorsement key certificate, per the TCG PC
+/// Client Platform TPM Profile specification.
+pub const RSA_EK_CERT_NV_INDEX: u32 = 0x01c0_0002;
+
+/// `TPMA_NV` attributes for a platform-owned, platform-readable certificate
+/// index: writable and readable only with platform authorization, no
+/// dictionary-attack lockout, and created under the platform hierarchy.
+const EK_CERT_NV_ATTRIBUTES: u32 = (1 << 0) | (1 << 16) | (1 << 18) | (1 << 25) | (1 << 30);
+
+/// Largest chunk written by a single `TPM2_NV_Write`. Backends negotiate
+/// their own maximum NV buffer size, but every implementation this crate
+/// talks to accepts at least this much in one command.
+const NV_WRITE_CHUNK_SIZE: usize = 1024;
+
+/// Builds the auth area for the empty-password platform hierarchy session,
+/// shared by every command in this module.
+fn empty_pw_auth_area() -> Vec<u8> {
+    let mut auth_area = Vec::with_capacity(9);
+    auth_area.extend_from_slice(&TPM_RS_PW.to_be_bytes());
+    auth_area.extend_from_slice(&0u16.to_be_bytes()); // nonce size
+    auth_area.push(0); // session attributes
+    auth_area.extend_from_slice(&0u16.to_be_bytes()); // hmac size
+    auth_area
+}
+
+/// Builds `TPM2_NV_DefineSpace(TPM_RH_PLATFORM, nvIndex, dataSize)`,
+/// authorized with the platform hierarchy's empty-password session.
+fn nv_define_space_command(nv_index: u32, data_size: u16) -> Vec<u8> {
+    let auth_area = empty_pw_auth_area();
+
+    let mut nv_public = Vec::with_capacity(14);
+    nv_public.extend_from_slice(&nv_index.to_be_bytes());
+    nv_public.extend_from_slice(&TPM_ALG_SHA256.to_be_bytes());
+    nv_public.extend_from_slice(&EK_CERT_NV_ATTRIBUTES.to_be_bytes());
+    nv_public.extend_from_slice(&0u16.to_be_bytes()); // authPolicy size
+    nv_public.extend_from_slice(&data_size.to_be_bytes());
+
+    let mut public_info = Vec::with_capacity(2 + nv_public.len());
+    public_info.extend_from_slice(&(nv_public.len() as u16).to_be_bytes());
+    public_info.extend_from_slice(&nv_public);
+
+    let command_size =
+        2 + 4 + 4 + 4 + 4 + auth_area.len() + 2 /* empty TPM2B_AUTH */ + public_info.len();
+    let mut cmd = Vec::with_capacity(command_size);
+    cmd.extend_from_slice(&TPM_ST_SESSIONS.to_be_bytes());
+    cmd.extend_from_slice(&(command_size as u32).to_be_bytes());
+    cmd.extend_from_slice(&TPM_CC_NV_DEFINE_SPACE.to_be_bytes());
+    cmd.extend_from_slice(&TPM_RH_PLATFORM.to_be_bytes());
+    cmd.extend_from_slice(&(auth_area.len() as u32).to_be_bytes());
+    cmd.extend_from_slice(&auth_area);
+    cmd.extend_from_slice(&0u16.to_be_bytes()); // auth (TPM2B_AUTH), empty
+    cmd.extend_from_slice(&public_info);
+    cmd
+}
+
+/// Builds `TPM2_NV_Write(TPM_RH_PLATFORM, nvIndex, data, offset)`, authorized
+/// the same way as [`nv_define_space_command`].
+fn nv_write_command(nv_index: u32, data: &[u8], offset: u16) -> Vec<u8> {
+    let auth_area = empty_pw_auth_area();
+
+    let command_size = 2 + 4 + 4 + 4 + 4 + 4 + auth_area.len() + 2 + data.len() + 2;
+    let mut cmd = Vec::with_capacity(command_size);
+    cmd.extend_from_slice(&TPM_ST_SESSIONS.to_be_bytes());
+    cmd.extend_from_slice(&(command_size as u32).to_be_bytes());
+    cmd.extend_from_slice(&TPM_CC_NV_WRITE.to_be_bytes());
+    cmd.extend_from_slice(&TPM_RH_PLATFORM.to_be_bytes());
+    cmd.extend_from_slice(&nv_index.to_be_bytes());
+    cmd.extend_from_slice(&(auth_area.len() as u32).to_be_bytes());
+    cmd.extend_from_slice(&auth_area);
+    cmd.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    cmd.extend_from_slice(data);
+    cmd.extend_from_slice(&offset.to_be_bytes());
+    cmd
+}
+
+fn response_code(response: &[u8]) -> Result<u32> {
+    Ok(u32::from_be_bytes(
+        response
+            .get(6..10)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    ))
+}
+
+/// Defines `nv_index` and writes `cert` into it, chunked across as many
+/// `TPM2_NV_Write` commands as needed. Meant to be called once, right after
+/// [`TpmBackend::startup`], so the certificate is in place before guest
+/// firmware gets a chance to run.
+///
+/// Re-provisioning an index that is already defined (e.g. a restart after a
+/// previous successful run) is treated as success rather than an error, so
+/// this is safe to call on every VM start.
+pub fn provision_ek_cert(backend: &mut dyn TpmBackend, nv_index: u32, cert: &[u8]) -> Result<()> {
+    let define_response =
+        backend.deliver_request(&nv_define_space_command(nv_index, cert.len() as u16))?;
+    let define_code = response_code(&define_response)?;
+    if define_code != TPM_RC_SUCCESS && define_code != TPM_RC_NV_DEFINED {
+        return Err(Error::TpmCommandFailed(define_code));
+    }
+
+    for (chunk_index, chunk) in cert.chunks(NV_WRITE_CHUNK_SIZE).enumerate() {
+        let offset = (chunk_index * NV_WRITE_CHUNK_SIZE) as u16;
+        let response = backend.deliver_request(&nv_write_command(nv_index, chunk, offset))?;
+        let code = response_code(&response)?;
+        if code != TPM_RC_SUCCESS {
+            return Err(Error::TpmCommandFailed(code));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct RecordingBackend {
+        command_codes: Mutex<Vec<u32>>,
+        define_response_code: u32,
+        write_response_code: u32,
+    }
+
+    fn fixed_response(code: u32) -> Vec<u8> {
+        let mut response = Vec::new();
+        response.extend_from_slice(&0x8001u16.to_be_bytes());
+        response.extend_from_slice(&10u32.to_be_bytes());
+        response.extend_from_slice(&code.to_be_bytes());
+        response
+    }
+
+    impl TpmBackend for RecordingBackend {
+        fn startup(&mut self, _init: crate::ptm::PtmInit) -> Result<()> {
+            Ok(())
+        }
+
+        fn store_volatile(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+            let command_code = u32::from_be_bytes(cmd[6..10].try_into().unwrap());
+            self.command_codes.lock().unwrap().push(command_code);
+
+            let code = if command_code == TPM_CC_NV_DEFINE_SPACE {
+                self.define_response_code
+            } else {
+                self.write_response_code
+            };
+            Ok(fixed_response(code))
+        }
+
+        fn cancel_cmd(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_established_flag(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_buffer_size(&mut self, requested: u32) -> Result<crate::ptm::PtmSetBufferSize> {
+            Ok(crate::ptm::PtmSetBufferSize {
+                buffersize: requested,
+                minsize: requested,
+                maxsize: requested,
+            })
+        }
+
+        fn hash_start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_end(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_config(&mut self) -> Result<crate::ptm::PtmGetConfig> {
+            Ok(crate::ptm::PtmGetConfig { flags: 0 })
+        }
+
+        fn get_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _passphrase: Option<&[u8]>,
+        ) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn set_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _data: &[u8],
+            _passphrase: Option<&[u8]>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_provision_ek_cert_defines_and_writes_a_small_cert() {
+        let mut backend = RecordingBackend {
+            command_codes: Mutex::new(Vec::new()),
+            define_response_code: TPM_RC_SUCCESS,
+            write_response_code: TPM_RC_SUCCESS,
+        };
+
+        provision_ek_cert(&mut backend, RSA_EK_CERT_NV_INDEX, &[0xaa; 512]).unwrap();
+
+        let codes = backend.command_codes.into_inner().unwrap();
+        assert_eq!(codes, vec![TPM_CC_NV_DEFINE_SPACE, TPM_CC_NV_WRITE]);
+    }
+
+    #[test]
+    fn test_provision_ek_cert_chunks_a_large_cert_across_multiple_writes() {
+        let mut backend = RecordingBackend {
+            command_codes: Mutex::new(Vec::new()),
+            define_response_code: TPM_RC_SUCCESS,
+            write_response_code: TPM_RC_SUCCESS,
+        };
+
+        provision_ek_cert(
+            &mut backend,
+            RSA_EK_CERT_NV_INDEX,
+            &[0xbb; NV_WRITE_CHUNK_SIZE * 2 + 1],
+        )
+        .unwrap();
+
+        let codes = backend.command_codes.into_inner().unwrap();
+        assert_eq!(
+            codes,
+            vec![
+                TPM_CC_NV_DEFINE_SPACE,
+                TPM_CC_NV_WRITE,
+                TPM_CC_NV_WRITE,
+                TPM_CC_NV_WRITE
+            ]
+        );
+    }
+
+    #[test]
+    fn test_provision_ek_cert_tolerates_an_already_defined_index() {
+        let mut backend = RecordingBackend {
+            command_codes: Mutex::new(Vec::new()),
+            define_response_code: TPM_RC_NV_DEFINED,
+            write_response_code: TPM_RC_SUCCESS,
+        };
+
+        provision_ek_cert(&mut backend, RSA_EK_CERT_NV_INDEX, &[0xcc; 16]).unwrap();
+    }
+
+    #[test]
+    fn test_provision_ek_cert_reports_a_failed_write() {
+        let mut backend = RecordingBackend {
+            command_codes: Mutex::new(Vec::new()),
+            define_response_code: TPM_RC_SUCCESS,
+            write_response_code: 0x0144, // some non-zero TPM_RC
+        };
+
+        let err = provision_ek_cert(&mut backend, RSA_EK_CERT_NV_INDEX, &[0xdd; 16]).unwrap_err();
+        assert!(matches!(err, Error::TpmCommandFailed(0x0144)));
+    }
+}
@@ -0,0 +1,1015 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+use crate::ptm::{
+    Capabilities, Commands, PtmCap, PtmDecode, PtmEncode, PtmEst, PtmGetConfig, PtmGetInfo,
+    PtmGetInfoHeader, PtmGetState, PtmGetStateResponseHeader, PtmHData, PtmInit, PtmLoc,
+    PtmSetBufferSize, PtmSetState, StateBlobType, TPM_SUCCESS,
+};
+
+/// How many times to re-dial both sockets after a disconnect, and how long
+/// to wait between attempts, before giving up and reporting the original
+/// I/O error to the caller.
+///
+/// The delay doubles after every failed attempt, starting from
+/// `initial_delay` and capped at `max_delay`, giving a restarting swtpm
+/// time to re-create its listening sockets without either hammering it
+/// with back-to-back dial attempts or, at the other extreme, blocking VM
+/// boot on a long fixed wait when swtpm actually comes up quickly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Upper bound on the `responseSize` a data channel response header is
+/// allowed to claim. Guards against `deliver_request_once` allocating an
+/// unreasonably large `Vec` on the strength of a single untrusted length
+/// field from a misbehaving backend; real TPM responses are nowhere near
+/// this size.
+const MAX_RESPONSE_SIZE: usize = 1 << 20;
+
+/// Largest single chunk [`TpmEmulator::set_state_blob`] sends in one
+/// `CmdSetStateBlob` request. Splitting larger blobs across several chunks
+/// mirrors the chunked framing [`TpmEmulator::get_state_blob`] already has
+/// to handle on the receiving end, and keeps either direction from forcing
+/// a single oversized control channel message through the same buffer size
+/// `CmdSetBufferSize` negotiates for the data channel.
+const SET_STATE_BLOB_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Whether a [`TpmEmulator`] is actively processing commands, transiently
+/// paused, or permanently torn down.
+///
+/// swtpm's control channel has two commands that both look like "turn the
+/// TPM off" but aren't: `CmdStop` ([`TpmBackend::stop`]) pauses it in place,
+/// ready to resume with another `CmdInit` (used here to bracket a buffer
+/// size renegotiation, the same way a real firmware driver would wrap a
+/// config change that can't be applied to a live TPM, and by a caller like
+/// `TpmTisCore::resume` that needs to make sure it isn't sending a second
+/// `CmdInit` to a backend that's still running), while `CmdShutdown` tears
+/// it down for good. Without tracking which one happened, a caller has no
+/// way to tell "send `CmdInit` to resume" and "the process behind the
+/// control channel is gone" apart other than by trying and seeing which I/O
+/// error comes back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EmulatorLifecycle {
+    Running,
+    Stopped,
+    Shutdown,
+}
+
+/// Either side of a connected transport a [`TpmEmulator`] can be told to
+/// use, so the same framing/reconnect logic works whether swtpm is reachable
+/// over a local Unix socket or over TCP (e.g. running in a different
+/// network namespace or on a separate host).
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(s) => s.read(buf),
+            Stream::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Unix(s) => s.write(buf),
+            Stream::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Unix(s) => s.flush(),
+            Stream::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+/// Where to (re)connect a [`TpmEmulator`]'s control and data channels.
+///
+/// Both channels are always dialed as independent connections up front,
+/// Unix or TCP alike; there is no `CmdSetDatafd`-style negotiation where the
+/// data channel starts out as an fd handed over the control channel (see
+/// [`crate::ptm::Commands::CmdSetDatafd`]), so there is nothing here that
+/// needs a fallback for a transport where fd passing isn't available.
+enum Endpoint {
+    Unix { ctrl_path: PathBuf, data_path: PathBuf },
+    /// `ctrl_port` is the control channel; by convention the data channel
+    /// is the next port up, mirroring how the two Unix sockets are named
+    /// (one path, one derived from it) rather than requiring a second port
+    /// to be configured explicitly.
+    Tcp { host: String, ctrl_port: u16 },
+}
+
+impl Endpoint {
+    fn connect(&self) -> Result<(Stream, Stream)> {
+        match self {
+            Endpoint::Unix {
+                ctrl_path,
+                data_path,
+            } => {
+                let ctrl = UnixStream::connect(ctrl_path).map_err(Error::Connect)?;
+                let data = UnixStream::connect(data_path).map_err(Error::Connect)?;
+                Ok((Stream::Unix(ctrl), Stream::Unix(data)))
+            }
+            Endpoint::Tcp { host, ctrl_port } => {
+                let ctrl = TcpStream::connect((host.as_str(), *ctrl_port)).map_err(Error::Connect)?;
+                let data =
+                    TcpStream::connect((host.as_str(), *ctrl_port + 1)).map_err(Error::Connect)?;
+                Ok((Stream::Tcp(ctrl), Stream::Tcp(data)))
+            }
+        }
+    }
+}
+
+/// Client for an external `swtpm` process, talking the control channel
+/// protocol over one connection and exchanging TPM command/response blobs
+/// over a second, dedicated data channel connection.
+///
+/// If swtpm is restarted, both connections are closed from the other end;
+/// rather than leaving the device permanently broken, every control/data
+/// channel exchange transparently reconnects and replays the handshake
+/// (`CmdInit`, then re-negotiating the buffer size) on an `EPIPE`/
+/// `ECONNRESET`-style error before retrying once.
+///
+/// `ctrl`/`data` are `None` rather than connected from construction when
+/// built via [`TpmEmulator::new_deferred`]/[`TpmEmulator::new_tcp_deferred`]:
+/// swtpm may not be listening yet, and the first real dial attempt is
+/// deferred to [`TpmBackend::ensure_connected`], called from every other
+/// trait method before it touches the streams.
+pub struct TpmEmulator {
+    ctrl: Option<Stream>,
+    data: Option<Stream>,
+    endpoint: Endpoint,
+    /// Buffer size last negotiated with the backend, if any, replayed after
+    /// a reconnect so the TIS front-end's view of it stays valid.
+    buffer_size: Option<u32>,
+    /// Result of the first [`TpmBackend::capabilities`] probe, cached so
+    /// later callers don't round-trip `CmdGetCapability` again: the set of
+    /// commands a connected swtpm implements doesn't change over the life
+    /// of the connection.
+    capabilities: Option<Capabilities>,
+    /// The `PtmInit` last passed to [`TpmBackend::startup`], replayed as
+    /// `CmdInit` whenever a connection (first or reconnected) is dialed.
+    /// `None` only for a deferred connection whose `startup` hasn't been
+    /// called yet, in which case connecting replays the default.
+    pending_init: Option<PtmInit>,
+    /// Retry count/backoff used by [`TpmEmulator::reconnect`].
+    reconnect_policy: ReconnectPolicy,
+    /// Tracks `CmdStop`/`CmdShutdown` so every other [`TpmBackend`] method
+    /// can refuse to run against a backend that isn't actually expecting
+    /// commands; see [`EmulatorLifecycle`].
+    lifecycle: EmulatorLifecycle,
+}
+
+impl TpmEmulator {
+    /// Connect to an already-running `swtpm` instance listening on
+    /// `ctrl_path` (control channel) and `data_path` (data channel) Unix
+    /// sockets.
+    pub fn new<P: AsRef<Path>>(
+        ctrl_path: P,
+        data_path: P,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Result<Self> {
+        let mut emulator = Self::new_deferred(ctrl_path, data_path, reconnect_policy);
+        emulator.ensure_connected()?;
+        Ok(emulator)
+    }
+
+    /// Like [`TpmEmulator::new`], but doesn't dial `swtpm` yet. The
+    /// connection is made lazily by [`TpmBackend::ensure_connected`], called
+    /// at the top of every other `TpmBackend` method, so constructing this
+    /// doesn't fail just because swtpm hasn't started listening yet.
+    pub fn new_deferred<P: AsRef<Path>>(
+        ctrl_path: P,
+        data_path: P,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Self {
+        TpmEmulator {
+            ctrl: None,
+            data: None,
+            endpoint: Endpoint::Unix {
+                ctrl_path: ctrl_path.as_ref().to_path_buf(),
+                data_path: data_path.as_ref().to_path_buf(),
+            },
+            buffer_size: None,
+            capabilities: None,
+            pending_init: None,
+            reconnect_policy,
+            lifecycle: EmulatorLifecycle::Running,
+        }
+    }
+
+    /// Connect to an already-running `swtpm` instance exposing its control
+    /// channel over TCP at `(host, ctrl_port)`, with the data channel
+    /// expected one port above it.
+    pub fn new_tcp(
+        host: impl Into<String>,
+        ctrl_port: u16,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Result<Self> {
+        let mut emulator = Self::new_tcp_deferred(host, ctrl_port, reconnect_policy);
+        emulator.ensure_connected()?;
+        Ok(emulator)
+    }
+
+    /// Like [`TpmEmulator::new_tcp`], but doesn't dial `swtpm` yet; see
+    /// [`TpmEmulator::new_deferred`].
+    pub fn new_tcp_deferred(
+        host: impl Into<String>,
+        ctrl_port: u16,
+        reconnect_policy: ReconnectPolicy,
+    ) -> Self {
+        TpmEmulator {
+            ctrl: None,
+            data: None,
+            endpoint: Endpoint::Tcp {
+                host: host.into(),
+                ctrl_port,
+            },
+            buffer_size: None,
+            capabilities: None,
+            pending_init: None,
+            reconnect_policy,
+            lifecycle: EmulatorLifecycle::Running,
+        }
+    }
+
+    /// Returns an error if the backend isn't currently expecting commands,
+    /// i.e. it's between a `CmdStop` and the `CmdInit` that resumes it, or
+    /// it's been sent `CmdShutdown` for good. Called at the top of every
+    /// [`TpmBackend`] method that actually needs the TPM to execute
+    /// something, before [`TpmEmulator::ensure_connected`] would otherwise
+    /// try to dial (or redial) a backend that isn't listening for commands
+    /// regardless of whether the socket is still up.
+    fn ensure_running(&self) -> Result<()> {
+        match self.lifecycle {
+            EmulatorLifecycle::Running => Ok(()),
+            EmulatorLifecycle::Stopped => Err(Error::NotRunning("stopped")),
+            EmulatorLifecycle::Shutdown => Err(Error::NotRunning("shut down")),
+        }
+    }
+
+    /// Send a command and payload on the control channel, and read back the
+    /// four byte result code. Does not read any response payload: callers
+    /// that expect one follow up with [`TpmEmulator::read_ctrl_payload`].
+    /// Transparently reconnects and retries once if the channel was found
+    /// disconnected.
+    fn send_ctrl_cmd(&mut self, cmd: Commands, payload: &[u8]) -> Result<()> {
+        match self.send_ctrl_cmd_once(cmd, payload) {
+            Err(e) if e.is_disconnect() => {
+                self.ctrl = None;
+                self.data = None;
+                self.reconnect()?;
+                self.send_ctrl_cmd_once(cmd, payload)
+            }
+            result => result,
+        }
+    }
+
+    fn send_ctrl_cmd_once(&mut self, cmd: Commands, payload: &[u8]) -> Result<()> {
+        let mut buf = vec![0u8; 4 + payload.len()];
+        BigEndian::write_u32(&mut buf[0..4], cmd as u32);
+        buf[4..].copy_from_slice(payload);
+        self.ctrl_mut().write_all(&buf).map_err(Error::Send)?;
+
+        let mut res_buf = [0u8; 4];
+        self.ctrl_mut().read_exact(&mut res_buf).map_err(Error::Recv)?;
+        let res = BigEndian::read_u32(&res_buf);
+        if res != TPM_SUCCESS {
+            return Err(Error::CommandFailed(res));
+        }
+        Ok(())
+    }
+
+    fn read_ctrl_payload(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.ctrl_mut().read_exact(buf).map_err(Error::Recv)
+    }
+
+    /// Panics if called before [`TpmEmulator::ensure_connected`] has
+    /// succeeded; every [`TpmBackend`] method calls it first, so by the time
+    /// any of the helpers below run `self.ctrl`/`self.data` are always
+    /// `Some`.
+    fn ctrl_mut(&mut self) -> &mut Stream {
+        self.ctrl.as_mut().expect("ensure_connected runs first")
+    }
+
+    fn data_mut(&mut self) -> &mut Stream {
+        self.data.as_mut().expect("ensure_connected runs first")
+    }
+
+    /// Re-dial both sockets, retrying the dial itself per
+    /// `self.reconnect_policy` since swtpm may still be tearing down its old
+    /// listening sockets right after a restart (or, for a deferred
+    /// connection, hasn't started listening yet at all). The delay between
+    /// attempts doubles each time, starting from `initial_delay` and capped
+    /// at `max_delay`.
+    fn reconnect(&mut self) -> Result<()> {
+        let mut delay = self.reconnect_policy.initial_delay;
+        let mut last_err = None;
+        for attempt in 0..self.reconnect_policy.max_attempts {
+            if attempt > 0 {
+                thread::sleep(delay);
+                delay = (delay * 2).min(self.reconnect_policy.max_delay);
+            }
+            match self.connect_once() {
+                Ok(()) => return Ok(()),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.expect("loop runs at least once"))
+    }
+
+    /// Dial both sockets and, if [`TpmBackend::startup`] has already been
+    /// called (`pending_init` is `Some`), replay the handshake a connection
+    /// needs (`CmdInit`, then re-negotiating whatever buffer size was last
+    /// in effect). `pending_init` is still `None` only for the very first
+    /// connect of a backend that hasn't had `startup` called on it yet, in
+    /// which case the caller sends `CmdInit` itself right after.
+    fn connect_once(&mut self) -> Result<()> {
+        let (ctrl, data) = self.endpoint.connect()?;
+        self.ctrl = Some(ctrl);
+        self.data = Some(data);
+
+        let Some(init) = self.pending_init else {
+            return Ok(());
+        };
+        self.send_ctrl_cmd_once(Commands::CmdInit, &init.encode())?;
+
+        if let Some(buffersize) = self.buffer_size {
+            let request = PtmSetBufferSize {
+                buffersize,
+                ..Default::default()
+            };
+            self.send_ctrl_cmd_once(Commands::CmdSetBufferSize, &request.encode())?;
+            let mut buf = [0u8; 12];
+            self.read_ctrl_payload(&mut buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// `write_all`/`read_exact` already loop internally over however many
+    /// partial writes or reads the kernel splits a transfer into (and retry
+    /// on `EINTR`), so a short write on `self.data` or a `read_exact` call
+    /// returning fewer bytes than asked for part way through a response is
+    /// handled without any extra bookkeeping here. The framing itself is
+    /// still explicit: the response's own 10 byte header carries its total
+    /// length, and that's what decides how many more bytes to read rather
+    /// than assuming a single read (or a single underlying socket message)
+    /// ever carries the whole response.
+    fn deliver_request_once(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+        self.data_mut().write_all(cmd).map_err(Error::DataSend)?;
+
+        let mut header = [0u8; 10];
+        self.data_mut()
+            .read_exact(&mut header)
+            .map_err(Error::DataRecv)?;
+        let resp_len = BigEndian::read_u32(&header[2..6]) as usize;
+        if resp_len < header.len() || resp_len > MAX_RESPONSE_SIZE {
+            return Err(Error::MalformedResponse);
+        }
+
+        let mut response = header.to_vec();
+        response.resize(resp_len, 0);
+        self.data_mut()
+            .read_exact(&mut response[header.len()..])
+            .map_err(Error::DataRecv)?;
+        Ok(response)
+    }
+}
+
+impl TpmBackend for TpmEmulator {
+    fn startup(&mut self, init: PtmInit) -> Result<()> {
+        self.pending_init = Some(init);
+        if self.ctrl.is_none() {
+            // Deferred connection that hasn't been dialed yet: record the
+            // init we'd otherwise send now, and replay it as `CmdInit` once
+            // `ensure_connected` actually dials.
+            self.lifecycle = EmulatorLifecycle::Running;
+            return Ok(());
+        }
+        self.send_ctrl_cmd(Commands::CmdInit, &init.encode())?;
+        // `CmdInit` is also how a backend paused with `CmdStop` resumes, so
+        // a caller can call `startup` again to bring a `Stopped` emulator
+        // back to `Running` rather than needing a separate "resume" method.
+        self.lifecycle = EmulatorLifecycle::Running;
+        Ok(())
+    }
+
+    fn ensure_connected(&mut self) -> Result<()> {
+        if self.ctrl.is_some() {
+            return Ok(());
+        }
+        self.reconnect()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.ctrl.is_some()
+    }
+
+    fn store_volatile(&mut self) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdStoreVolatile, &[])
+    }
+
+    fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        match self.deliver_request_once(cmd) {
+            Err(e) if e.is_disconnect() => {
+                self.ctrl = None;
+                self.data = None;
+                self.reconnect()?;
+                self.deliver_request_once(cmd)
+            }
+            result => result,
+        }
+    }
+
+    fn cancel_cmd(&mut self) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdCancelTpmCmd, &[])
+    }
+
+    fn get_established_flag(&mut self) -> Result<bool> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdGetTpmEstablished, &[])?;
+        let mut buf = [0u8; 1];
+        self.read_ctrl_payload(&mut buf)?;
+        Ok(PtmEst::decode(&buf).bit != 0)
+    }
+
+    fn reset_established_flag(&mut self, locality: u8) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdResetTpmEstablished, &PtmLoc { loc: locality }.encode())
+    }
+
+    fn set_locality(&mut self, locality: u8) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdSetLocality, &PtmLoc { loc: locality }.encode())
+    }
+
+    fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize> {
+        // Pause the backend for the duration of the renegotiation rather
+        // than sending `CmdSetBufferSize` to a live TPM: `CmdInit` below
+        // resumes it once the new size is in effect, the same way a real
+        // guest-visible `TPM2_Startup` would follow a config change that
+        // can't be applied while the TPM is running.
+        self.stop()?;
+
+        let request = PtmSetBufferSize {
+            buffersize: requested,
+            ..Default::default()
+        };
+        self.send_ctrl_cmd(Commands::CmdSetBufferSize, &request.encode())?;
+        let mut buf = [0u8; 12];
+        self.read_ctrl_payload(&mut buf)?;
+        let negotiated = PtmSetBufferSize::decode(&buf);
+        self.buffer_size = Some(negotiated.buffersize);
+
+        if let Some(init) = self.pending_init {
+            self.send_ctrl_cmd(Commands::CmdInit, &init.encode())?;
+        }
+        self.lifecycle = EmulatorLifecycle::Running;
+
+        Ok(negotiated)
+    }
+
+    fn hash_start(&mut self) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdHashStart, &[])
+    }
+
+    fn hash_data(&mut self, data: &[u8]) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(
+            Commands::CmdHashData,
+            &PtmHData {
+                data: data.to_vec(),
+            }
+            .encode(),
+        )
+    }
+
+    fn hash_end(&mut self) -> Result<()> {
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdHashEnd, &[])
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        // Idempotent: a VM already torn down shouldn't fail to shut down
+        // again just because the process behind the control channel is
+        // gone by the time a second caller gets to it.
+        if self.lifecycle == EmulatorLifecycle::Shutdown {
+            return Ok(());
+        }
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdShutdown, &[])?;
+        self.lifecycle = EmulatorLifecycle::Shutdown;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        // Idempotent for the same reason `shutdown` is: a caller that just
+        // wants to make sure `startup` is safe to call again shouldn't have
+        // to first find out whether this backend is already stopped.
+        if self.lifecycle == EmulatorLifecycle::Stopped {
+            return Ok(());
+        }
+        self.ensure_running()?;
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdStop, &[])?;
+        self.lifecycle = EmulatorLifecycle::Stopped;
+        Ok(())
+    }
+
+    fn get_config(&mut self) -> Result<PtmGetConfig> {
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdGetConfig, &[])?;
+        let mut buf = [0u8; 4];
+        self.read_ctrl_payload(&mut buf)?;
+        Ok(PtmGetConfig::decode(&buf))
+    }
+
+    fn get_info(&mut self) -> Result<PtmGetInfo> {
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdGetInfo, &[])?;
+
+        let mut header_buf = [0u8; 8];
+        self.read_ctrl_payload(&mut header_buf)?;
+        let header = PtmGetInfoHeader::decode(&header_buf);
+
+        let mut build_info_buf = vec![0u8; header.build_info_len as usize];
+        self.read_ctrl_payload(&mut build_info_buf)?;
+
+        Ok(PtmGetInfo {
+            version: header.version,
+            build_info: String::from_utf8_lossy(&build_info_buf).into_owned(),
+        })
+    }
+
+    fn get_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        self.ensure_connected()?;
+        let passphrase = passphrase.unwrap_or(&[]).to_vec();
+
+        let mut data = Vec::new();
+        loop {
+            let request = PtmGetState {
+                state_blob_type: blob_type,
+                passphrase: passphrase.clone(),
+                offset: data.len() as u32,
+            };
+            self.send_ctrl_cmd(Commands::CmdGetStateBlob, &request.encode())?;
+
+            let mut header_buf = [0u8; 12];
+            self.read_ctrl_payload(&mut header_buf)?;
+            let header = PtmGetStateResponseHeader::decode(&header_buf);
+
+            let mut chunk = vec![0u8; header.length as usize];
+            self.read_ctrl_payload(&mut chunk)?;
+            data.extend_from_slice(&chunk);
+
+            if !header.has_more_data() {
+                break;
+            }
+        }
+        Ok(data)
+    }
+
+    fn set_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        data: &[u8],
+        passphrase: Option<&[u8]>,
+    ) -> Result<()> {
+        self.ensure_connected()?;
+        let passphrase = passphrase.unwrap_or(&[]).to_vec();
+
+        // Always send at least one chunk, even for an empty blob, so
+        // `data.chunks()` (which yields nothing for an empty slice) doesn't
+        // skip the transfer entirely.
+        let mut chunks = data.chunks(SET_STATE_BLOB_CHUNK_SIZE).peekable();
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let is_final_chunk = chunks.peek().is_none();
+            let request = PtmSetState {
+                state_blob_type: blob_type,
+                passphrase: passphrase.clone(),
+                data: chunk.to_vec(),
+                is_final_chunk,
+            };
+            self.send_ctrl_cmd(Commands::CmdSetStateBlob, &request.encode())?;
+            if is_final_chunk {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        if let Some(caps) = self.capabilities {
+            return Ok(caps);
+        }
+
+        self.ensure_connected()?;
+        self.send_ctrl_cmd(Commands::CmdGetCapability, &[])?;
+        let mut buf = [0u8; 8];
+        self.read_ctrl_payload(&mut buf)?;
+        let caps = Capabilities::from_raw(PtmCap::decode(&buf).caps);
+        self.capabilities = Some(caps);
+        Ok(caps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockSwtpm;
+
+    /// Builds a [`TpmEmulator`] directly from an already-connected pair of
+    /// streams, skipping the Unix-socket-path dialing `TpmEmulator::new`
+    /// does, so tests can drive it against a `UnixStream::pair()` rather
+    /// than a real filesystem socket.
+    fn test_emulator(ctrl: UnixStream, data: UnixStream) -> TpmEmulator {
+        TpmEmulator {
+            ctrl: Some(Stream::Unix(ctrl)),
+            data: Some(Stream::Unix(data)),
+            endpoint: Endpoint::Unix {
+                ctrl_path: PathBuf::new(),
+                data_path: PathBuf::new(),
+            },
+            buffer_size: None,
+            capabilities: None,
+            pending_init: Some(PtmInit::default()),
+            reconnect_policy: ReconnectPolicy::default(),
+            lifecycle: EmulatorLifecycle::Running,
+        }
+    }
+
+    /// [`TpmEmulator::deliver_request_once`] builds the response up with
+    /// `read_exact`, which itself loops over however many individual `recv`s
+    /// the kernel chooses to split a write into and retries on `EINTR` -
+    /// unlike a single-shot `recvfrom` call, there's no assumption that one
+    /// socket read returns the peer's whole write. This drives that case
+    /// directly with a `socketpair`-style connected stream (see
+    /// [`UnixStream::pair`]) whose other end deliberately trickles the
+    /// response out one byte at a time.
+    #[test]
+    fn test_deliver_request_once_reassembles_response_sent_in_small_chunks() {
+        let (data, peer) = UnixStream::pair().unwrap();
+        let (ctrl, _ctrl_peer) = UnixStream::pair().unwrap();
+        let mut emulator = test_emulator(ctrl, data);
+
+        let server = thread::spawn(move || {
+            let mut peer = peer;
+            let mut cmd = [0u8; 10];
+            peer.read_exact(&mut cmd).unwrap();
+
+            let mut response = vec![0x80, 0x01];
+            response.extend_from_slice(&20u32.to_be_bytes()); // resp_len
+            response.extend_from_slice(&0u32.to_be_bytes()); // TPM_RC_SUCCESS
+            response.extend_from_slice(&[0xaa; 10]); // body past the header
+
+            for byte in response {
+                peer.write_all(&[byte]).unwrap();
+            }
+        });
+
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes());
+        let response = emulator.deliver_request(&command).unwrap();
+
+        server.join().unwrap();
+        assert_eq!(response.len(), 20);
+        assert_eq!(&response[10..], &[0xaa; 10]);
+    }
+
+    #[test]
+    fn test_deliver_request_once_rejects_response_shorter_than_its_own_header() {
+        let (data, peer) = UnixStream::pair().unwrap();
+        let (ctrl, _ctrl_peer) = UnixStream::pair().unwrap();
+        let mut emulator = test_emulator(ctrl, data);
+
+        let server = thread::spawn(move || {
+            let mut peer = peer;
+            let mut cmd = [0u8; 10];
+            peer.read_exact(&mut cmd).unwrap();
+
+            let mut response = vec![0x80, 0x01];
+            response.extend_from_slice(&4u32.to_be_bytes()); // resp_len smaller than the 10 byte header
+            response.extend_from_slice(&0u32.to_be_bytes());
+            peer.write_all(&response).unwrap();
+        });
+
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes());
+        let err = emulator.deliver_request(&command).unwrap_err();
+
+        server.join().unwrap();
+        assert!(matches!(err, Error::MalformedResponse));
+    }
+
+    #[test]
+    fn test_deliver_request_once_rejects_implausibly_large_response_size() {
+        let (data, peer) = UnixStream::pair().unwrap();
+        let (ctrl, _ctrl_peer) = UnixStream::pair().unwrap();
+        let mut emulator = test_emulator(ctrl, data);
+
+        let server = thread::spawn(move || {
+            let mut peer = peer;
+            let mut cmd = [0u8; 10];
+            peer.read_exact(&mut cmd).unwrap();
+
+            let mut response = vec![0x80, 0x01];
+            response.extend_from_slice(&u32::MAX.to_be_bytes()); // implausible resp_len
+            response.extend_from_slice(&0u32.to_be_bytes());
+            peer.write_all(&response).unwrap();
+        });
+
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes());
+        let err = emulator.deliver_request(&command).unwrap_err();
+
+        server.join().unwrap();
+        assert!(matches!(err, Error::MalformedResponse));
+    }
+
+    #[test]
+    fn test_capabilities_is_probed_once_and_cached() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        let (ctrl, peer) = UnixStream::pair().unwrap();
+        let (data, _data_peer) = UnixStream::pair().unwrap();
+        let mut emulator = test_emulator(ctrl, data);
+
+        let request_count = Arc::new(AtomicU32::new(0));
+        let request_count_server = Arc::clone(&request_count);
+        let server = thread::spawn(move || {
+            let mut peer = peer;
+            let mut header = [0u8; 4];
+            if peer.read_exact(&mut header).is_err() {
+                return;
+            }
+            request_count_server.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(BigEndian::read_u32(&header), Commands::CmdGetCapability as u32);
+            peer.write_all(&0u32.to_be_bytes()).unwrap();
+            peer.write_all(&crate::ptm::cap_bits::CANCEL_TPM_CMD.to_be_bytes())
+                .unwrap();
+
+            // A second probe would show up as another request here; reading
+            // this out just confirms there isn't one once the emulator (and
+            // with it, this socket) is dropped.
+            let mut extra = [0u8; 4];
+            let _ = peer.read_exact(&mut extra);
+        });
+
+        let first = emulator.capabilities().unwrap();
+        let second = emulator.capabilities().unwrap();
+        assert_eq!(first, second);
+        assert!(first.supports_cancel());
+
+        drop(emulator);
+        server.join().unwrap();
+        assert_eq!(request_count.load(Ordering::SeqCst), 1);
+    }
+
+    /// Exercises [`TpmEmulator::new_tcp`] against a pair of loopback
+    /// listeners on adjacent ports, standing in for a real TCP-exposed
+    /// swtpm. Candidate ports are retried a few times since nothing
+    /// reserves the pair in advance of binding both.
+    #[test]
+    fn test_new_tcp_connects_ctrl_and_data_on_adjacent_ports() {
+        use std::net::TcpListener;
+
+        let (ctrl_listener, data_listener, ctrl_port) = (0..16)
+            .find_map(|_| {
+                let ctrl_listener = TcpListener::bind("127.0.0.1:0").ok()?;
+                let ctrl_port = ctrl_listener.local_addr().ok()?.port();
+                let data_listener = TcpListener::bind(("127.0.0.1", ctrl_port + 1)).ok()?;
+                Some((ctrl_listener, data_listener, ctrl_port))
+            })
+            .expect("found an adjacent pair of free loopback ports");
+
+        let server = thread::spawn(move || {
+            let (mut ctrl, _) = ctrl_listener.accept().unwrap();
+            let (mut data, _) = data_listener.accept().unwrap();
+
+            let mut header = [0u8; 4];
+            ctrl.read_exact(&mut header).unwrap();
+            let mut payload = [0u8; 4];
+            ctrl.read_exact(&mut payload).unwrap();
+            ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+
+            let mut tag_len = [0u8; 10];
+            data.read_exact(&mut tag_len).unwrap();
+            let mut resp = vec![0x80, 0x01];
+            resp.extend_from_slice(&10u32.to_be_bytes());
+            resp.extend_from_slice(&0u32.to_be_bytes());
+            data.write_all(&resp).unwrap();
+        });
+
+        let mut emulator = TpmEmulator::new_tcp("127.0.0.1", ctrl_port, ReconnectPolicy::default()).unwrap();
+        emulator.startup(PtmInit::default()).unwrap();
+
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes());
+        let response = emulator.deliver_request(&command).unwrap();
+        assert_eq!(response.len(), 10);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_emulator_end_to_end() {
+        let mock = MockSwtpm::new("end_to_end");
+        let mut emulator = TpmEmulator::new(&mock.ctrl_path, &mock.data_path, ReconnectPolicy::default()).unwrap();
+
+        emulator.startup(PtmInit::default()).unwrap();
+        let negotiated = emulator.set_buffer_size(4096).unwrap();
+        assert_eq!(negotiated.buffersize, 4096);
+        assert_eq!(negotiated.minsize, 128);
+        assert_eq!(negotiated.maxsize, 4096);
+        assert!(!emulator.get_established_flag().unwrap());
+        let config = emulator.get_config().unwrap();
+        assert_eq!(config.flags & crate::ptm::TPM_CONFIG_FLAG_STATE_ENCRYPTION, crate::ptm::TPM_CONFIG_FLAG_STATE_ENCRYPTION);
+        emulator.reset_established_flag(3).unwrap();
+        emulator.set_locality(0).unwrap();
+        emulator.cancel_cmd().unwrap();
+        emulator.store_volatile().unwrap();
+
+        let caps = emulator.capabilities().unwrap();
+        assert!(caps.supports_cancel());
+        assert!(caps.supports_stateblob());
+        assert!(!caps.supports_get_config(), "MockSwtpm's canned caps don't set GET_CONFIG");
+
+        let blob = emulator
+            .get_state_blob(StateBlobType::Permanent, Some(b"passphrase"))
+            .unwrap();
+        assert_eq!(blob, b"fake-state-blob");
+        emulator
+            .set_state_blob(StateBlobType::Permanent, &blob, Some(b"passphrase"))
+            .unwrap();
+
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes()); // TPM2_SelfTest
+        let response = emulator.deliver_request(&command).unwrap();
+        assert_eq!(response.len(), 10);
+    }
+
+    #[test]
+    fn test_shutdown_rejects_further_commands_with_a_typed_error() {
+        let mock = MockSwtpm::new("shutdown_lifecycle");
+        let mut emulator = TpmEmulator::new(&mock.ctrl_path, &mock.data_path, ReconnectPolicy::default()).unwrap();
+        emulator.startup(PtmInit::default()).unwrap();
+
+        emulator.shutdown().unwrap();
+        // Calling it again shouldn't try to reach an already-torn-down
+        // backend a second time.
+        emulator.shutdown().unwrap();
+
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes());
+        let err = emulator.deliver_request(&command).unwrap_err();
+        assert!(matches!(err, Error::NotRunning("shut down")));
+    }
+
+    #[test]
+    fn test_set_buffer_size_stops_and_resumes_the_backend() {
+        let mock = MockSwtpm::new("buffer_size_lifecycle");
+        let mut emulator = TpmEmulator::new(&mock.ctrl_path, &mock.data_path, ReconnectPolicy::default()).unwrap();
+        emulator.startup(PtmInit::default()).unwrap();
+
+        emulator.set_buffer_size(4096).unwrap();
+        assert_eq!(emulator.lifecycle, EmulatorLifecycle::Running);
+
+        // The backend resumed, so a command right after renegotiation
+        // should go through rather than being rejected as not running.
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes());
+        let response = emulator.deliver_request(&command).unwrap();
+        assert_eq!(response.len(), 10);
+    }
+
+    #[test]
+    fn test_deferred_connect_waits_until_first_access() {
+        let mock = MockSwtpm::new("deferred_connect");
+        let mut emulator = TpmEmulator::new_deferred(&mock.ctrl_path, &mock.data_path, ReconnectPolicy::default());
+        assert!(!emulator.is_connected());
+
+        // `startup` just records the init for later; it doesn't dial.
+        emulator.startup(PtmInit::default()).unwrap();
+        assert!(!emulator.is_connected());
+
+        emulator.ensure_connected().unwrap();
+        assert!(emulator.is_connected());
+        assert!(emulator.store_volatile().is_ok());
+    }
+
+    #[test]
+    fn test_deferred_connect_dials_implicitly_on_first_command() {
+        let mock = MockSwtpm::new("deferred_connect_implicit");
+        let mut emulator = TpmEmulator::new_deferred(&mock.ctrl_path, &mock.data_path, ReconnectPolicy::default());
+        emulator.startup(PtmInit::default()).unwrap();
+
+        let mut command = vec![0x80, 0x01];
+        command.extend_from_slice(&10u32.to_be_bytes());
+        command.extend_from_slice(&0x143u32.to_be_bytes());
+        let response = emulator.deliver_request(&command).unwrap();
+        assert_eq!(response.len(), 10);
+        assert!(emulator.is_connected());
+    }
+
+    #[test]
+    fn test_deferred_connect_fails_until_swtpm_is_listening() {
+        let ctrl_path = std::path::PathBuf::from(format!(
+            "/tmp/vtpm_test_never_listens_{}.ctrl",
+            std::process::id()
+        ));
+        let data_path = std::path::PathBuf::from(format!(
+            "/tmp/vtpm_test_never_listens_{}.data",
+            std::process::id()
+        ));
+        let mut emulator = TpmEmulator::new_deferred(&ctrl_path, &data_path, ReconnectPolicy::default());
+        emulator.startup(PtmInit::default()).unwrap();
+        assert!(emulator.ensure_connected().is_err());
+        assert!(!emulator.is_connected());
+    }
+
+    #[test]
+    fn test_get_info_decodes_version_and_build_info() {
+        let (ctrl, peer) = UnixStream::pair().unwrap();
+        let (data, _data_peer) = UnixStream::pair().unwrap();
+        let mut emulator = test_emulator(ctrl, data);
+
+        let server = thread::spawn(move || {
+            let mut peer = peer;
+            let mut header = [0u8; 4];
+            peer.read_exact(&mut header).unwrap();
+            assert_eq!(BigEndian::read_u32(&header), Commands::CmdGetInfo as u32);
+            peer.write_all(&0u32.to_be_bytes()).unwrap();
+
+            let build_info = b"swtpm 0.8.0";
+            peer.write_all(&0x0102_0300u32.to_be_bytes()).unwrap();
+            peer.write_all(&(build_info.len() as u32).to_be_bytes())
+                .unwrap();
+            peer.write_all(build_info).unwrap();
+        });
+
+        let info = emulator.get_info().unwrap();
+        assert_eq!(info.version, 0x0102_0300);
+        assert_eq!(info.build_info, "swtpm 0.8.0");
+
+        server.join().unwrap();
+    }
+}
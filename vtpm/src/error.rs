@@ -0,0 +1,60 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to connect to the swtpm control socket: {0}")]
+    Connect(#[source] io::Error),
+    #[error("failed to send command on the control channel: {0}")]
+    Send(#[source] io::Error),
+    #[error("failed to read response from the control channel: {0}")]
+    Recv(#[source] io::Error),
+    #[error("failed to send command on the data channel: {0}")]
+    DataSend(#[source] io::Error),
+    #[error("failed to read response from the data channel: {0}")]
+    DataRecv(#[source] io::Error),
+    #[error("swtpm control command returned a non zero status: {0:#x}")]
+    CommandFailed(u32),
+    #[error("TPM2 command returned a non zero response code: {0:#x}")]
+    TpmCommandFailed(u32),
+    #[error("response from swtpm was truncated or malformed")]
+    MalformedResponse,
+    #[error("backend did not respond to the command within the configured timeout")]
+    CommandTimedOut,
+    #[error("in-process TPM simulator error: {0}")]
+    Simulator(String),
+    #[error("failed to set ownership or permissions on the TPM state directory: {0}")]
+    StateDirPermissions(#[source] io::Error),
+    #[error("operation not supported by this TPM backend")]
+    Unsupported,
+    #[error("TPM backend cannot accept commands because it is {0}")]
+    NotRunning(&'static str),
+}
+
+impl Error {
+    /// Whether this error indicates the underlying socket was closed from
+    /// the other end (e.g. swtpm restarted), as opposed to a malformed
+    /// protocol exchange that reconnecting wouldn't fix.
+    pub(crate) fn is_disconnect(&self) -> bool {
+        let kind = match self {
+            Error::Connect(e)
+            | Error::Send(e)
+            | Error::Recv(e)
+            | Error::DataSend(e)
+            | Error::DataRecv(e) => e.kind(),
+            _ => return false,
+        };
+        matches!(
+            kind,
+            io::ErrorKind::BrokenPipe
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+        )
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
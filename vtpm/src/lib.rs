@@ -0,0 +1,44 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Support for virtual TPM devices backed by an external `swtpm` process.
+//!
+//! This crate only speaks the `swtpm` control/data channel protocol and
+//! exposes it behind the [`TpmBackend`] trait; the guest-facing TIS/CRB
+//! register interfaces live in the `devices` crate.
+//!
+//! [`TpmBackend`] is already the pluggable-transport seam: [`TpmEmulator`]
+//! talks to an external process over a pair of `AF_UNIX` sockets or, for a
+//! swtpm reachable over TCP, a pair of TCP streams, using the same plain
+//! `write_all`/`read_exact` framing either way, while [`TpmSimulator`] runs a
+//! TPM 2.0 implementation in-process with no socket at all, and
+//! [`policy::DenyListBackend`] wraps either one.
+
+mod backend;
+pub mod capability;
+pub mod clear;
+pub mod ek_cert;
+mod emulator;
+mod error;
+pub mod policy;
+pub mod ptm;
+pub mod random;
+pub mod rc;
+mod selftest;
+pub mod shutdown;
+mod simulator;
+pub mod state_dir;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_support;
+
+pub use backend::TpmBackend;
+pub use capability::PcrBank;
+pub use emulator::{ReconnectPolicy, TpmEmulator};
+pub use error::{Error, Result};
+pub use policy::DenyListBackend;
+pub use random::get_random;
+pub use rc::{decode as decode_rc, DecodedRc, TpmRcSubject};
+pub use selftest::run_self_test;
+pub use simulator::TpmSimulator;
+pub use state_dir::secure_state_dir;
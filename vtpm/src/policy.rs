@@ -0,0 +1,302 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! An optional [`TpmBackend`] wrapper that refuses to run configured TPM2
+//! commands, for hosts that want to take commands like `TPM2_Clear` or NV
+//! writes off the table for every guest regardless of that guest's own TPM
+//! policy.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::backend::TpmBackend;
+use crate::clear::TPM_CC_CLEAR;
+use crate::ptm::{Capabilities, PtmGetConfig, PtmGetInfo, PtmInit, PtmSetBufferSize, StateBlobType};
+use crate::Result;
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+
+/// `TPM_RC_COMMAND_CODE`: the standard TPM2 response code for "the TPM does
+/// not support (or refuses to execute) this command", reused here as the
+/// cleanest way to tell the guest "no" without it looking like a transport
+/// failure.
+const TPM_RC_COMMAND_CODE: u32 = 0x143;
+
+/// Builds a header-only `TPM_RC_COMMAND_CODE` response: tag, responseSize
+/// (always 10, the header's own length) and responseCode, with no body.
+fn command_denied_response() -> Vec<u8> {
+    let mut response = Vec::with_capacity(10);
+    response.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+    response.extend_from_slice(&10u32.to_be_bytes());
+    response.extend_from_slice(&TPM_RC_COMMAND_CODE.to_be_bytes());
+    response
+}
+
+/// Pulls the `commandCode` (TPM2 command ordinal) out of a raw command
+/// blob's header, or `None` if it's too short to have one.
+fn command_ordinal(cmd: &[u8]) -> Option<u32> {
+    cmd.get(6..10)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+/// Wraps another [`TpmBackend`] and denies any command whose ordinal is in
+/// `deny_list`, answering the guest with [`TPM_RC_COMMAND_CODE`] instead of
+/// forwarding the command to `inner`. Every other [`TpmBackend`] method
+/// passes straight through.
+pub struct DenyListBackend {
+    inner: Box<dyn TpmBackend>,
+    deny_list: HashSet<u32>,
+    /// When set, a denied `TPM2_Clear` is also persisted here via
+    /// [`crate::clear::request_clear`] so a host that refuses to clear a
+    /// running TPM can still honor the request the next time the VM boots
+    /// (see [`crate::clear::apply_pending_clear`]).
+    pending_clear_marker: Option<PathBuf>,
+}
+
+impl DenyListBackend {
+    pub fn new(
+        inner: Box<dyn TpmBackend>,
+        deny_list: impl IntoIterator<Item = u32>,
+        pending_clear_marker: Option<PathBuf>,
+    ) -> Self {
+        DenyListBackend {
+            inner,
+            deny_list: deny_list.into_iter().collect(),
+            pending_clear_marker,
+        }
+    }
+}
+
+impl TpmBackend for DenyListBackend {
+    fn startup(&mut self, init: PtmInit) -> Result<()> {
+        self.inner.startup(init)
+    }
+
+    fn store_volatile(&mut self) -> Result<()> {
+        self.inner.store_volatile()
+    }
+
+    fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+        if let Some(ordinal) = command_ordinal(cmd) {
+            if self.deny_list.contains(&ordinal) {
+                if ordinal == TPM_CC_CLEAR {
+                    if let Some(marker) = &self.pending_clear_marker {
+                        let _ = crate::clear::request_clear(marker);
+                    }
+                }
+                return Ok(command_denied_response());
+            }
+        }
+        self.inner.deliver_request(cmd)
+    }
+
+    fn cancel_cmd(&mut self) -> Result<()> {
+        self.inner.cancel_cmd()
+    }
+
+    fn get_established_flag(&mut self) -> Result<bool> {
+        self.inner.get_established_flag()
+    }
+
+    fn reset_established_flag(&mut self, locality: u8) -> Result<()> {
+        self.inner.reset_established_flag(locality)
+    }
+
+    fn set_locality(&mut self, locality: u8) -> Result<()> {
+        self.inner.set_locality(locality)
+    }
+
+    fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize> {
+        self.inner.set_buffer_size(requested)
+    }
+
+    fn hash_start(&mut self) -> Result<()> {
+        self.inner.hash_start()
+    }
+
+    fn hash_data(&mut self, data: &[u8]) -> Result<()> {
+        self.inner.hash_data(data)
+    }
+
+    fn hash_end(&mut self) -> Result<()> {
+        self.inner.hash_end()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.inner.stop()
+    }
+
+    fn get_config(&mut self) -> Result<PtmGetConfig> {
+        self.inner.get_config()
+    }
+
+    fn get_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        self.inner.get_state_blob(blob_type, passphrase)
+    }
+
+    fn set_state_blob(
+        &mut self,
+        blob_type: StateBlobType,
+        data: &[u8],
+        passphrase: Option<&[u8]>,
+    ) -> Result<()> {
+        self.inner.set_state_blob(blob_type, data, passphrase)
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        self.inner.capabilities()
+    }
+
+    fn ensure_connected(&mut self) -> Result<()> {
+        self.inner.ensure_connected()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn get_info(&mut self) -> Result<PtmGetInfo> {
+        self.inner.get_info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EchoBackend;
+
+    impl TpmBackend for EchoBackend {
+        fn startup(&mut self, _init: PtmInit) -> Result<()> {
+            Ok(())
+        }
+
+        fn store_volatile(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+            Ok(cmd.to_vec())
+        }
+
+        fn cancel_cmd(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_established_flag(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize> {
+            Ok(PtmSetBufferSize {
+                buffersize: requested,
+                minsize: requested,
+                maxsize: requested,
+            })
+        }
+
+        fn hash_start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_end(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_config(&mut self) -> Result<PtmGetConfig> {
+            Ok(PtmGetConfig { flags: 0 })
+        }
+
+        fn get_state_blob(
+            &mut self,
+            _blob_type: StateBlobType,
+            _passphrase: Option<&[u8]>,
+        ) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn set_state_blob(
+            &mut self,
+            _blob_type: StateBlobType,
+            _data: &[u8],
+            _passphrase: Option<&[u8]>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn command(ordinal: u32) -> Vec<u8> {
+        let mut cmd = Vec::new();
+        cmd.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        cmd.extend_from_slice(&10u32.to_be_bytes());
+        cmd.extend_from_slice(&ordinal.to_be_bytes());
+        cmd
+    }
+
+    #[test]
+    fn test_denied_command_gets_command_code_error_without_reaching_inner() {
+        let mut backend = DenyListBackend::new(Box::new(EchoBackend), [0x0000_0126], None);
+        let response = backend.deliver_request(&command(0x0000_0126)).unwrap();
+        assert_eq!(response.len(), 10, "a denial response is header-only");
+        let response_code = u32::from_be_bytes(response[6..10].try_into().unwrap());
+        assert_eq!(response_code, TPM_RC_COMMAND_CODE);
+    }
+
+    #[test]
+    fn test_allowed_command_reaches_inner_backend() {
+        let mut backend = DenyListBackend::new(Box::new(EchoBackend), [0x0000_0126], None);
+        let cmd = command(0x0000_0144); // TPM2_Startup, not denied here
+        let response = backend.deliver_request(&cmd).unwrap();
+        assert_eq!(response, cmd, "EchoBackend echoes whatever it is handed");
+    }
+
+    #[test]
+    fn test_short_command_without_an_ordinal_is_not_denied() {
+        let mut backend = DenyListBackend::new(Box::new(EchoBackend), [0x0000_0126], None);
+        let response = backend.deliver_request(&[1, 2, 3]).unwrap();
+        assert_eq!(response, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_denying_clear_persists_a_pending_clear_marker() {
+        let tmp_dir = std::env::temp_dir().join(format!(
+            "ch-tpm-deny-clear-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let marker = crate::clear::marker_path(&tmp_dir);
+
+        let mut backend =
+            DenyListBackend::new(Box::new(EchoBackend), [TPM_CC_CLEAR], Some(marker.clone()));
+        backend.deliver_request(&command(TPM_CC_CLEAR)).unwrap();
+
+        assert!(marker.exists(), "denying a clear should persist it for next boot");
+
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+    }
+}
@@ -0,0 +1,549 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wire definitions for the swtpm "PTM" (Pass-through TPM Master) control
+//! channel protocol. Every request on the control channel is a big-endian
+//! `u32` command code, optionally followed by a command-specific payload;
+//! the response is a big-endian `u32` `tpm_result` optionally followed by
+//! a command-specific payload.
+
+use byteorder::{BigEndian, ByteOrder};
+
+/// Control channel command codes, as understood by swtpm's `ctrlchannel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Commands {
+    CmdGetCapability = 1,
+    CmdInit = 2,
+    CmdShutdown = 3,
+    CmdGetTpmEstablished = 4,
+    CmdSetLocality = 5,
+    CmdHashStart = 6,
+    CmdHashData = 7,
+    CmdHashEnd = 8,
+    CmdCancelTpmCmd = 9,
+    CmdStoreVolatile = 10,
+    CmdResetTpmEstablished = 11,
+    CmdGetStateBlob = 12,
+    CmdSetStateBlob = 13,
+    CmdStop = 14,
+    CmdGetConfig = 15,
+    /// Listed for ordinal completeness against swtpm's own `ctrlchannel`
+    /// numbering; never sent by [`crate::emulator::TpmEmulator`]. That
+    /// command hands the data channel to swtpm as an already-connected file
+    /// descriptor over `SCM_RIGHTS`, which only makes sense when control and
+    /// data share one transport to pass it over. This client always dials
+    /// control and data as two independent connections from the start
+    /// (`ctrl_path`/`data_path`, or the control port and the port above it
+    /// over TCP), so the data channel is never without a connection for
+    /// `CmdSetDatafd` to hand one over, on a Unix socket or otherwise.
+    #[allow(dead_code)]
+    CmdSetDatafd = 16,
+    CmdSetBufferSize = 17,
+    CmdGetInfo = 18,
+}
+
+/// Standard header returned by every control channel command: a four byte
+/// big-endian result code, zero on success.
+pub type PtmRes = u32;
+
+/// A fixed-shape control channel request payload, encoded big-endian the
+/// same way for every command: this replaces hand-rolling
+/// `BigEndian::write_u32`/byte pushes per call site with one `encode()` each
+/// [`crate::emulator::TpmEmulator`] method can pass straight to
+/// `send_ctrl_cmd`.
+pub trait PtmEncode {
+    fn encode(&self) -> Vec<u8>;
+}
+
+/// A fixed-shape control channel response payload. `buf` is exactly the
+/// payload bytes read off the wire for the command that produced it; callers
+/// are expected to have already read that many bytes before decoding.
+pub trait PtmDecode: Sized {
+    fn decode(buf: &[u8]) -> Self;
+}
+
+/// Payload for `CmdGetCapability`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmCap {
+    pub caps: u64,
+}
+
+impl PtmDecode for PtmCap {
+    fn decode(buf: &[u8]) -> Self {
+        PtmCap {
+            caps: BigEndian::read_u64(buf),
+        }
+    }
+}
+
+/// Named bits of [`PtmCap::caps`]/[`Capabilities`], one per control channel
+/// command a backend may or may not implement. Not every swtpm build
+/// implements every command (older builds predate some of these), which is
+/// the whole reason to probe rather than assume.
+pub mod cap_bits {
+    pub const INIT: u64 = 1 << 0;
+    pub const SHUTDOWN: u64 = 1 << 1;
+    pub const GET_TPMESTABLISHED: u64 = 1 << 2;
+    pub const SET_LOCALITY: u64 = 1 << 3;
+    pub const HASHING: u64 = 1 << 4;
+    pub const CANCEL_TPM_CMD: u64 = 1 << 5;
+    pub const STORE_VOLATILE: u64 = 1 << 6;
+    pub const RESET_TPMESTABLISHED: u64 = 1 << 7;
+    pub const GET_STATEBLOB: u64 = 1 << 8;
+    pub const SET_STATEBLOB: u64 = 1 << 9;
+    pub const STOP: u64 = 1 << 10;
+    pub const GET_CONFIG: u64 = 1 << 11;
+    pub const SET_DATAFD: u64 = 1 << 12;
+    pub const SET_BUFFERSIZE: u64 = 1 << 13;
+    pub const GET_INFO: u64 = 1 << 14;
+}
+
+/// Typed view of which control channel commands a backend implements,
+/// resolved once (see [`crate::TpmBackend::capabilities`]) rather than
+/// sprinkling raw [`PtmCap::caps`] bit tests across call sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    /// Every capability bit set. The right answer for a backend (e.g.
+    /// [`crate::TpmSimulator`]) that doesn't speak the PTM control channel
+    /// at all and so has no raw [`PtmCap`] to decode in the first place;
+    /// such a backend reports what it behaviorally supports by overriding
+    /// [`crate::TpmBackend::capabilities`] directly instead.
+    pub fn all() -> Self {
+        Capabilities(u64::MAX)
+    }
+
+    /// Decodes a raw [`PtmCap::caps`] bitmask as reported by `CmdGetCapability`.
+    pub fn from_raw(caps: u64) -> Self {
+        Capabilities(caps)
+    }
+
+    /// Clears `bits` from an otherwise-supported set of capabilities, for a
+    /// backend that behaviorally lacks a specific command without going
+    /// through a real `CmdGetCapability` probe.
+    pub fn without(self, bits: u64) -> Self {
+        Capabilities(self.0 & !bits)
+    }
+
+    fn has(self, bits: u64) -> bool {
+        self.0 & bits == bits
+    }
+
+    /// Whether `CmdCancelTpmCmd` is implemented.
+    pub fn supports_cancel(self) -> bool {
+        self.has(cap_bits::CANCEL_TPM_CMD)
+    }
+
+    /// Whether both `CmdGetStateBlob` and `CmdSetStateBlob` are implemented,
+    /// i.e. whether this backend's state can round-trip through a VM
+    /// snapshot at all.
+    pub fn supports_stateblob(self) -> bool {
+        self.has(cap_bits::GET_STATEBLOB | cap_bits::SET_STATEBLOB)
+    }
+
+    /// Whether `CmdGetConfig` is implemented.
+    pub fn supports_get_config(self) -> bool {
+        self.has(cap_bits::GET_CONFIG)
+    }
+
+    /// Whether `CmdSetBufferSize` is implemented.
+    pub fn supports_buffer_size(self) -> bool {
+        self.has(cap_bits::SET_BUFFERSIZE)
+    }
+}
+
+/// Payload for `CmdInit`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmInit {
+    pub init_flags: u32,
+}
+
+impl PtmEncode for PtmInit {
+    fn encode(&self) -> Vec<u8> {
+        self.init_flags.to_be_bytes().to_vec()
+    }
+}
+
+/// Set in [`PtmInit::init_flags`] to discard any volatile state the backend
+/// may have stored (e.g. via `CmdStoreVolatile`) instead of reloading it, as
+/// is appropriate for a guest-triggered TPM reset but not for resuming from
+/// a pause.
+pub const PTM_INIT_FLAG_DELETE_VOLATILE: u32 = 0x1;
+
+/// Payload for `CmdGetTpmEstablished` responses.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmEst {
+    pub bit: u8,
+}
+
+impl PtmDecode for PtmEst {
+    fn decode(buf: &[u8]) -> Self {
+        PtmEst { bit: buf[0] }
+    }
+}
+
+/// Payload for `CmdSetLocality`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmLoc {
+    pub loc: u8,
+}
+
+impl PtmEncode for PtmLoc {
+    fn encode(&self) -> Vec<u8> {
+        vec![self.loc]
+    }
+}
+
+/// Payload for `CmdSetBufferSize`: `buffersize` is the requested size on the
+/// way in (0 queries the current size); the response carries the size that
+/// was actually negotiated along with the backend's supported range.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmSetBufferSize {
+    pub buffersize: u32,
+    pub minsize: u32,
+    pub maxsize: u32,
+}
+
+impl PtmEncode for PtmSetBufferSize {
+    fn encode(&self) -> Vec<u8> {
+        self.buffersize.to_be_bytes().to_vec()
+    }
+}
+
+impl PtmDecode for PtmSetBufferSize {
+    fn decode(buf: &[u8]) -> Self {
+        PtmSetBufferSize {
+            buffersize: BigEndian::read_u32(&buf[0..4]),
+            minsize: BigEndian::read_u32(&buf[4..8]),
+            maxsize: BigEndian::read_u32(&buf[8..12]),
+        }
+    }
+}
+
+/// Payload for `CmdGetConfig` responses: a bitmask of `TPM_CONFIG_*` flags
+/// describing how the backend was started (e.g. whether FIPS mode or a
+/// particular TPM family was selected).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmGetConfig {
+    pub flags: u32,
+}
+
+impl PtmDecode for PtmGetConfig {
+    fn decode(buf: &[u8]) -> Self {
+        PtmGetConfig {
+            flags: BigEndian::read_u32(buf),
+        }
+    }
+}
+
+/// Set when the backend's persisted state is encrypted at rest (swtpm
+/// `--key`/`--pwdfile`), one of the bits of [`PtmGetConfig::flags`].
+pub const TPM_CONFIG_FLAG_STATE_ENCRYPTION: u32 = 0x1;
+
+/// Payload for `CmdHashStart`/`CmdHashEnd`: locality the hash sequence is
+/// bound to.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmHashLoc {
+    pub loc: u8,
+}
+
+impl PtmEncode for PtmHashLoc {
+    fn encode(&self) -> Vec<u8> {
+        vec![self.loc]
+    }
+}
+
+/// Payload for `CmdHashData`: one chunk of the data being hashed.
+#[derive(Debug, Default, Clone)]
+pub struct PtmHData {
+    pub data: Vec<u8>,
+}
+
+impl PtmEncode for PtmHData {
+    fn encode(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
+
+/// Payload for `CmdGetInfo` responses: backend version/build information.
+#[derive(Debug, Default, Clone)]
+pub struct PtmGetInfo {
+    pub version: u32,
+    pub build_info: String,
+}
+
+/// Fixed-size header of a `CmdGetInfo` response, read right after the
+/// command's leading `tpm_result`: the backend's version number and the
+/// length of the build info string that follows it on the wire, the same
+/// "fixed header, then a variable-length buffer" shape
+/// [`PtmGetStateResponseHeader`] uses for `CmdGetStateBlob`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmGetInfoHeader {
+    pub version: u32,
+    pub build_info_len: u32,
+}
+
+impl PtmDecode for PtmGetInfoHeader {
+    fn decode(buf: &[u8]) -> Self {
+        PtmGetInfoHeader {
+            version: BigEndian::read_u32(&buf[0..4]),
+            build_info_len: BigEndian::read_u32(&buf[4..8]),
+        }
+    }
+}
+
+/// The kinds of persisted state `CmdGetStateBlob`/`CmdSetStateBlob` can
+/// exchange with the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum StateBlobType {
+    Volatile = 1,
+    Permanent = 2,
+    SavedState = 3,
+}
+
+/// Set on a `CmdGetStateBlob`/`CmdSetStateBlob` request when the blob is
+/// encrypted and a passphrase accompanies it.
+pub const PTM_STATE_FLAG_ENCRYPTED: u32 = 0x1;
+
+/// Set on a `CmdSetStateBlob` request's header when `data` is not the final
+/// chunk of the blob, so the backend keeps accumulating bytes rather than
+/// committing what it has so far. Mirrored back on a `CmdGetStateBlob`
+/// response's header the same way, telling the caller it must issue a
+/// follow-up request at `offset` advanced by this chunk's length to fetch
+/// the rest; a response without this bit set carries the blob's last chunk.
+pub const PTM_STATE_FLAG_MORE_DATA: u32 = 0x2;
+
+/// Payload for `CmdGetStateBlob` requests: which blob to fetch, the
+/// passphrase to decrypt it with if it is encrypted at rest, and the offset
+/// to resume a chunked transfer from.
+#[derive(Debug, Clone)]
+pub struct PtmGetState {
+    pub state_blob_type: StateBlobType,
+    pub passphrase: Vec<u8>,
+    /// Byte offset into the blob to start this response's chunk from; zero
+    /// for the first request of a transfer, and the number of bytes already
+    /// received for every follow-up request needed because the prior
+    /// response set [`PTM_STATE_FLAG_MORE_DATA`].
+    pub offset: u32,
+}
+
+impl PtmEncode for PtmGetState {
+    fn encode(&self) -> Vec<u8> {
+        let mut payload = state_blob_header(self.state_blob_type, &self.passphrase, 0);
+        payload.extend_from_slice(&self.offset.to_be_bytes());
+        payload
+    }
+}
+
+/// Header of a `CmdGetStateBlob` response, read after the command's leading
+/// `tpm_result` has already been consumed by `send_ctrl_cmd`: whether more
+/// chunks remain, the blob's total length, and the length of the chunk that
+/// follows this header on the wire.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PtmGetStateResponseHeader {
+    pub state_flags: u32,
+    pub totlength: u32,
+    pub length: u32,
+}
+
+impl PtmGetStateResponseHeader {
+    pub fn has_more_data(&self) -> bool {
+        self.state_flags & PTM_STATE_FLAG_MORE_DATA != 0
+    }
+}
+
+impl PtmDecode for PtmGetStateResponseHeader {
+    fn decode(buf: &[u8]) -> Self {
+        PtmGetStateResponseHeader {
+            state_flags: BigEndian::read_u32(&buf[0..4]),
+            totlength: BigEndian::read_u32(&buf[4..8]),
+            length: BigEndian::read_u32(&buf[8..12]),
+        }
+    }
+}
+
+/// Payload for `CmdSetStateBlob` requests: which blob `data` is a chunk of,
+/// the passphrase it is encrypted with if any, and whether this chunk is
+/// the blob's last.
+#[derive(Debug, Clone)]
+pub struct PtmSetState {
+    pub state_blob_type: StateBlobType,
+    pub passphrase: Vec<u8>,
+    pub data: Vec<u8>,
+    /// Clears [`PTM_STATE_FLAG_MORE_DATA`] on the wire when true; set this
+    /// only on the last chunk of a transfer.
+    pub is_final_chunk: bool,
+}
+
+impl PtmEncode for PtmSetState {
+    fn encode(&self) -> Vec<u8> {
+        let extra_flags = if self.is_final_chunk {
+            0
+        } else {
+            PTM_STATE_FLAG_MORE_DATA
+        };
+        let mut payload = state_blob_header(self.state_blob_type, &self.passphrase, extra_flags);
+        payload.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        payload.extend_from_slice(&self.data);
+        payload
+    }
+}
+
+/// Common `[blob_type][flags][passphrase_len][passphrase]` prefix shared by
+/// [`PtmGetState`] and [`PtmSetState`]. `extra_flags` is ORed in alongside
+/// [`PTM_STATE_FLAG_ENCRYPTED`], e.g. [`PTM_STATE_FLAG_MORE_DATA`] for a
+/// non-final [`PtmSetState`] chunk.
+fn state_blob_header(state_blob_type: StateBlobType, passphrase: &[u8], extra_flags: u32) -> Vec<u8> {
+    let mut flags = extra_flags;
+    if !passphrase.is_empty() {
+        flags |= PTM_STATE_FLAG_ENCRYPTED;
+    }
+
+    let mut header = Vec::with_capacity(12 + passphrase.len());
+    header.extend_from_slice(&(state_blob_type as u32).to_be_bytes());
+    header.extend_from_slice(&flags.to_be_bytes());
+    header.extend_from_slice(&(passphrase.len() as u32).to_be_bytes());
+    header.extend_from_slice(passphrase);
+    header
+}
+
+pub const TPM_SUCCESS: PtmRes = 0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_all_reports_every_capability_supported() {
+        let caps = Capabilities::all();
+        assert!(caps.supports_cancel());
+        assert!(caps.supports_stateblob());
+        assert!(caps.supports_get_config());
+        assert!(caps.supports_buffer_size());
+    }
+
+    #[test]
+    fn test_capabilities_without_clears_just_the_given_bits() {
+        let caps = Capabilities::all().without(cap_bits::GET_STATEBLOB);
+        assert!(!caps.supports_stateblob(), "missing GET_STATEBLOB alone should fail the pair check");
+        assert!(caps.supports_cancel(), "unrelated bits should be untouched");
+    }
+
+    #[test]
+    fn test_capabilities_from_raw_requires_both_stateblob_bits() {
+        let get_only = Capabilities::from_raw(cap_bits::GET_STATEBLOB);
+        assert!(!get_only.supports_stateblob());
+
+        let both = Capabilities::from_raw(cap_bits::GET_STATEBLOB | cap_bits::SET_STATEBLOB);
+        assert!(both.supports_stateblob());
+    }
+
+    #[test]
+    fn test_capabilities_from_raw_reports_unset_bits_as_unsupported() {
+        let caps = Capabilities::from_raw(cap_bits::CANCEL_TPM_CMD);
+        assert!(caps.supports_cancel());
+        assert!(!caps.supports_get_config());
+        assert!(!caps.supports_buffer_size());
+    }
+
+    #[test]
+    fn test_hdata_encode_is_just_the_raw_chunk() {
+        let hdata = PtmHData {
+            data: vec![0xde, 0xad, 0xbe, 0xef],
+        };
+        assert_eq!(hdata.encode(), vec![0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn test_get_state_encode_matches_swtpm_request_layout() {
+        let request = PtmGetState {
+            state_blob_type: StateBlobType::Permanent,
+            passphrase: Vec::new(),
+            offset: 0,
+        };
+        assert_eq!(
+            request.encode(),
+            vec![
+                0, 0, 0, 2, // state_blob_type = Permanent
+                0, 0, 0, 0, // flags = 0, no passphrase
+                0, 0, 0, 0, // passphrase_len = 0
+                0, 0, 0, 0, // offset = 0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_state_encode_sets_encrypted_flag_and_carries_passphrase_and_offset() {
+        let request = PtmGetState {
+            state_blob_type: StateBlobType::SavedState,
+            passphrase: b"hunter2".to_vec(),
+            offset: 256,
+        };
+        let encoded = request.encode();
+        assert_eq!(&encoded[0..4], &3u32.to_be_bytes()); // SavedState
+        assert_eq!(&encoded[4..8], &PTM_STATE_FLAG_ENCRYPTED.to_be_bytes());
+        assert_eq!(&encoded[8..12], &7u32.to_be_bytes());
+        assert_eq!(&encoded[12..19], b"hunter2");
+        assert_eq!(&encoded[19..23], &256u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_get_state_response_header_decode_reports_more_data() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&PTM_STATE_FLAG_MORE_DATA.to_be_bytes());
+        buf.extend_from_slice(&4096u32.to_be_bytes());
+        buf.extend_from_slice(&1024u32.to_be_bytes());
+
+        let header = PtmGetStateResponseHeader::decode(&buf);
+        assert_eq!(header.totlength, 4096);
+        assert_eq!(header.length, 1024);
+        assert!(header.has_more_data());
+    }
+
+    #[test]
+    fn test_get_state_response_header_decode_reports_last_chunk() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        buf.extend_from_slice(&15u32.to_be_bytes());
+        buf.extend_from_slice(&15u32.to_be_bytes());
+
+        let header = PtmGetStateResponseHeader::decode(&buf);
+        assert!(!header.has_more_data());
+    }
+
+    #[test]
+    fn test_set_state_encode_matches_swtpm_request_layout_for_final_chunk() {
+        let request = PtmSetState {
+            state_blob_type: StateBlobType::Volatile,
+            passphrase: Vec::new(),
+            data: vec![1, 2, 3],
+            is_final_chunk: true,
+        };
+        assert_eq!(
+            request.encode(),
+            vec![
+                0, 0, 0, 1, // state_blob_type = Volatile
+                0, 0, 0, 0, // flags = 0: final chunk, no passphrase
+                0, 0, 0, 0, // passphrase_len = 0
+                0, 0, 0, 3, // data_len = 3
+                1, 2, 3, // data
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_state_encode_sets_more_data_flag_for_non_final_chunk() {
+        let request = PtmSetState {
+            state_blob_type: StateBlobType::Volatile,
+            passphrase: Vec::new(),
+            data: vec![1, 2, 3],
+            is_final_chunk: false,
+        };
+        let encoded = request.encode();
+        assert_eq!(&encoded[4..8], &PTM_STATE_FLAG_MORE_DATA.to_be_bytes());
+    }
+}
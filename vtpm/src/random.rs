@@ -0,0 +1,178 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! `TPM2_GetRandom` helper, mainly for the `vtpm-probe` binary to confirm a
+//! backend is actually answering TPM 2.0 commands rather than just the
+//! swtpm control channel handshake.
+//!
+//! Like [`crate::capability`] and [`crate::selftest`], this builds and
+//! parses an actual TPM2 command sent through [`crate::TpmBackend::deliver_request`]
+//! rather than speaking the swtpm control channel.
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_CC_GET_RANDOM: u32 = 0x0000_017b;
+const TPM_RC_SUCCESS: u32 = 0;
+
+/// Builds the fixed-size `TPM2_GetRandom(bytesRequested)` command. The
+/// command has no variable-length fields, so its encoding is always exactly
+/// this many bytes.
+fn get_random_command(bytes_requested: u16) -> Vec<u8> {
+    let mut cmd = Vec::with_capacity(12);
+    cmd.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+    cmd.extend_from_slice(&12u32.to_be_bytes()); // commandSize
+    cmd.extend_from_slice(&TPM_CC_GET_RANDOM.to_be_bytes());
+    cmd.extend_from_slice(&bytes_requested.to_be_bytes());
+    cmd
+}
+
+/// Issues `TPM2_GetRandom(bytesRequested)` through `backend` and returns the
+/// random bytes it reports. The backend is free to return fewer bytes than
+/// requested (the spec allows this), so the result should not be assumed to
+/// be exactly `bytes_requested` long.
+pub fn get_random(backend: &mut dyn TpmBackend, bytes_requested: u16) -> Result<Vec<u8>> {
+    let response = backend.deliver_request(&get_random_command(bytes_requested))?;
+
+    // Header: tag (2) + responseSize (4) + responseCode (4).
+    let response_code = u32::from_be_bytes(
+        response
+            .get(6..10)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    );
+    if response_code != TPM_RC_SUCCESS {
+        return Err(Error::TpmCommandFailed(response_code));
+    }
+
+    // `TPM2B_DIGEST randomBytes`: a two byte size prefix followed by that
+    // many bytes, starting right after the header.
+    let size = u16::from_be_bytes(
+        response
+            .get(10..12)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    ) as usize;
+    response
+        .get(12..12 + size)
+        .ok_or(Error::MalformedResponse)
+        .map(|bytes| bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(response_code: u32, random_bytes: &[u8]) -> Vec<u8> {
+        let mut response = Vec::new();
+        response.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        response.extend_from_slice(&10u32.to_be_bytes()); // responseSize, unused by the parser
+        response.extend_from_slice(&response_code.to_be_bytes());
+        if response_code == TPM_RC_SUCCESS {
+            response.extend_from_slice(&(random_bytes.len() as u16).to_be_bytes());
+            response.extend_from_slice(random_bytes);
+        }
+        response
+    }
+
+    struct StubBackend {
+        response: Vec<u8>,
+    }
+
+    impl TpmBackend for StubBackend {
+        fn startup(&mut self, _init: crate::ptm::PtmInit) -> Result<()> {
+            Ok(())
+        }
+
+        fn store_volatile(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn deliver_request(&mut self, _cmd: &[u8]) -> Result<Vec<u8>> {
+            Ok(self.response.clone())
+        }
+
+        fn cancel_cmd(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_established_flag(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_buffer_size(&mut self, requested: u32) -> Result<crate::ptm::PtmSetBufferSize> {
+            Ok(crate::ptm::PtmSetBufferSize {
+                buffersize: requested,
+                minsize: requested,
+                maxsize: requested,
+            })
+        }
+
+        fn hash_start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_end(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_config(&mut self) -> Result<crate::ptm::PtmGetConfig> {
+            Ok(crate::ptm::PtmGetConfig { flags: 0 })
+        }
+
+        fn get_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _passphrase: Option<&[u8]>,
+        ) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn set_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _data: &[u8],
+            _passphrase: Option<&[u8]>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_get_random_encodes_bytes_requested_and_parses_the_digest() {
+        let mut backend = StubBackend {
+            response: response(TPM_RC_SUCCESS, &[0xde, 0xad, 0xbe, 0xef]),
+        };
+        let bytes = get_random(&mut backend, 4).unwrap();
+        assert_eq!(bytes, vec![0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn test_get_random_propagates_a_non_success_response_code() {
+        let mut backend = StubBackend {
+            response: response(0x0922, &[]),
+        };
+        let err = get_random(&mut backend, 4).unwrap_err();
+        assert!(matches!(err, Error::TpmCommandFailed(0x0922)));
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decodes a `TPM_RC` response code into a human readable name, for logging
+//! and metrics when a guest command fails. See the TCG TPM2 Library spec,
+//! Part 2, section 6.6 ("TPM_RC"), for the bit layout this follows.
+//!
+//! This only covers the response codes useful for diagnosing a guest-visible
+//! command failure from the host side (lockouts, bad authorization, malformed
+//! commands, resource exhaustion); it does not attempt to be a complete
+//! mirror of the spec's response code table.
+
+const RC_FMT1: u32 = 0x080;
+const RC_WARN: u32 = 0x900;
+const FMT0_ERROR_MASK: u32 = 0x07f;
+const FMT1_ERROR_MASK: u32 = 0x03f;
+const FMT1_PARAMETER_FLAG: u32 = 0x040;
+const FMT1_NUMBER_MASK: u32 = 0xf00;
+const FMT1_NUMBER_SHIFT: u32 = 8;
+
+/// Which part of the command a format-one `TPM_RC` is attributed to (TCG
+/// TPM2 Library spec, Part 2, 6.6.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TpmRcSubject {
+    /// 1-based index into the command's handle area.
+    Handle(u8),
+    /// 1-based index into the command's session area.
+    Session(u8),
+    /// 1-based index into the command's parameter area.
+    Parameter(u8),
+}
+
+/// A `TPM_RC` response code, decoded into the fields worth surfacing in a
+/// host-side log line rather than the raw hex value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedRc {
+    /// The response code exactly as it appeared on the wire.
+    pub raw: u32,
+    /// Human readable name, e.g. `"TPM_RC_LOCKOUT"`, or `"TPM_RC_UNKNOWN"`
+    /// for a code this table doesn't recognize.
+    pub name: &'static str,
+    /// Set for a format-zero warning: the command may succeed if retried,
+    /// rather than having failed outright.
+    pub is_warning: bool,
+    /// Which handle, session or parameter a format-one code is attributed
+    /// to. Always `None` for a format-zero code, which carries no such
+    /// association.
+    pub subject: Option<TpmRcSubject>,
+}
+
+/// Decodes `rc` per the `TPM_RC` bit layout. `TPM_RC_SUCCESS` (`0`) decodes
+/// the same as any other format-zero, non-warning code, so callers should
+/// check for success before logging or counting a decoded code as a
+/// failure.
+pub fn decode(rc: u32) -> DecodedRc {
+    if rc & RC_FMT1 != 0 {
+        let error = rc & FMT1_ERROR_MASK;
+        let number = ((rc & FMT1_NUMBER_MASK) >> FMT1_NUMBER_SHIFT) as u8;
+        let subject = if rc & FMT1_PARAMETER_FLAG != 0 {
+            Some(TpmRcSubject::Parameter(number))
+        } else if number == 0 {
+            None
+        } else if number <= 7 {
+            Some(TpmRcSubject::Handle(number))
+        } else {
+            Some(TpmRcSubject::Session(number - 8))
+        };
+        DecodedRc {
+            raw: rc,
+            name: fmt1_name(error),
+            is_warning: false,
+            subject,
+        }
+    } else if rc & RC_WARN == RC_WARN {
+        DecodedRc {
+            raw: rc,
+            name: warning_name(rc & FMT0_ERROR_MASK),
+            is_warning: true,
+            subject: None,
+        }
+    } else {
+        DecodedRc {
+            raw: rc,
+            name: fmt0_name(rc & FMT0_ERROR_MASK),
+            is_warning: false,
+            subject: None,
+        }
+    }
+}
+
+fn fmt0_name(error: u32) -> &'static str {
+    match error {
+        0x000 => "TPM_RC_SUCCESS",
+        0x001 => "TPM_RC_FAILURE",
+        0x003 => "TPM_RC_SEQUENCE",
+        0x00b => "TPM_RC_PRIVATE",
+        0x019 => "TPM_RC_HMAC",
+        0x020 => "TPM_RC_DISABLED",
+        0x021 => "TPM_RC_EXCLUSIVE",
+        0x024 => "TPM_RC_AUTH_TYPE",
+        0x025 => "TPM_RC_AUTH_MISSING",
+        0x026 => "TPM_RC_POLICY",
+        0x027 => "TPM_RC_PCR",
+        0x028 => "TPM_RC_PCR_CHANGED",
+        0x02d => "TPM_RC_UPGRADE",
+        0x02e => "TPM_RC_TOO_MANY_CONTEXTS",
+        0x02f => "TPM_RC_AUTH_UNAVAILABLE",
+        0x030 => "TPM_RC_REBOOT",
+        0x031 => "TPM_RC_UNBALANCED",
+        0x042 => "TPM_RC_COMMAND_SIZE",
+        0x043 => "TPM_RC_COMMAND_CODE",
+        0x044 => "TPM_RC_AUTHSIZE",
+        0x045 => "TPM_RC_AUTH_CONTEXT",
+        0x046 => "TPM_RC_NV_RANGE",
+        0x047 => "TPM_RC_NV_SIZE",
+        0x048 => "TPM_RC_NV_LOCKED",
+        0x049 => "TPM_RC_NV_AUTHORIZATION",
+        0x04a => "TPM_RC_NV_UNINITIALIZED",
+        0x04b => "TPM_RC_NV_SPACE",
+        0x04c => "TPM_RC_NV_DEFINED",
+        0x050 => "TPM_RC_BAD_CONTEXT",
+        0x051 => "TPM_RC_CPHASH",
+        0x052 => "TPM_RC_PARENT",
+        0x053 => "TPM_RC_NEEDS_TEST",
+        0x054 => "TPM_RC_NO_RESULT",
+        0x055 => "TPM_RC_SENSITIVE",
+        _ => "TPM_RC_UNKNOWN",
+    }
+}
+
+fn fmt1_name(error: u32) -> &'static str {
+    match error {
+        0x001 => "TPM_RC_ASYMMETRIC",
+        0x002 => "TPM_RC_ATTRIBUTES",
+        0x003 => "TPM_RC_HASH",
+        0x004 => "TPM_RC_VALUE",
+        0x005 => "TPM_RC_HIERARCHY",
+        0x007 => "TPM_RC_KEY_SIZE",
+        0x008 => "TPM_RC_MGF",
+        0x009 => "TPM_RC_MODE",
+        0x00a => "TPM_RC_TYPE",
+        0x00b => "TPM_RC_HANDLE",
+        0x00c => "TPM_RC_KDF",
+        0x00d => "TPM_RC_RANGE",
+        0x00e => "TPM_RC_AUTH_FAIL",
+        0x00f => "TPM_RC_NONCE",
+        0x010 => "TPM_RC_PP",
+        0x012 => "TPM_RC_SCHEME",
+        0x015 => "TPM_RC_SIZE",
+        0x016 => "TPM_RC_SYMMETRIC",
+        0x017 => "TPM_RC_TAG",
+        0x018 => "TPM_RC_SELECTOR",
+        0x01a => "TPM_RC_INSUFFICIENT",
+        0x01b => "TPM_RC_SIGNATURE",
+        0x01c => "TPM_RC_KEY",
+        0x01d => "TPM_RC_POLICY_FAIL",
+        0x01f => "TPM_RC_INTEGRITY",
+        0x020 => "TPM_RC_TICKET",
+        0x021 => "TPM_RC_RESERVED_BITS",
+        0x022 => "TPM_RC_BAD_AUTH",
+        0x023 => "TPM_RC_EXPIRED",
+        0x024 => "TPM_RC_POLICY_CC",
+        0x025 => "TPM_RC_BINDING",
+        0x026 => "TPM_RC_CURVE",
+        0x027 => "TPM_RC_ECC_POINT",
+        _ => "TPM_RC_UNKNOWN",
+    }
+}
+
+fn warning_name(error: u32) -> &'static str {
+    match error {
+        0x001 => "TPM_RC_CONTEXT_GAP",
+        0x002 => "TPM_RC_OBJECT_MEMORY",
+        0x003 => "TPM_RC_SESSION_MEMORY",
+        0x004 => "TPM_RC_MEMORY",
+        0x005 => "TPM_RC_SESSION_HANDLES",
+        0x006 => "TPM_RC_OBJECT_HANDLES",
+        0x007 => "TPM_RC_LOCALITY",
+        0x008 => "TPM_RC_YIELDED",
+        0x009 => "TPM_RC_CANCELED",
+        0x00a => "TPM_RC_TESTING",
+        0x020 => "TPM_RC_NV_RATE",
+        0x021 => "TPM_RC_LOCKOUT",
+        0x022 => "TPM_RC_RETRY",
+        0x023 => "TPM_RC_NV_UNAVAILABLE",
+        _ => "TPM_RC_UNKNOWN",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_success() {
+        let decoded = decode(0x000);
+        assert_eq!(decoded.name, "TPM_RC_SUCCESS");
+        assert!(!decoded.is_warning);
+        assert_eq!(decoded.subject, None);
+    }
+
+    #[test]
+    fn test_decode_format_zero_warning_lockout() {
+        // RC_WARN (0x900) + error number 0x021.
+        let decoded = decode(0x921);
+        assert_eq!(decoded.name, "TPM_RC_LOCKOUT");
+        assert!(decoded.is_warning);
+        assert_eq!(decoded.subject, None);
+    }
+
+    #[test]
+    fn test_decode_format_zero_error_auth_missing() {
+        // RC_VER1 (0x100) + error number 0x025.
+        let decoded = decode(0x125);
+        assert_eq!(decoded.name, "TPM_RC_AUTH_MISSING");
+        assert!(!decoded.is_warning);
+    }
+
+    #[test]
+    fn test_decode_format_one_auth_fail_on_session_one() {
+        // RC_FMT1 (0x080) + error number 0x00e, N = 9 (session index 1).
+        let decoded = decode(0x080 | 0x00e | (9 << FMT1_NUMBER_SHIFT));
+        assert_eq!(decoded.name, "TPM_RC_AUTH_FAIL");
+        assert!(!decoded.is_warning);
+        assert_eq!(decoded.subject, Some(TpmRcSubject::Session(1)));
+    }
+
+    #[test]
+    fn test_decode_format_one_value_on_parameter_two() {
+        // RC_FMT1 (0x080) + P (0x040) + error number 0x004, N = 2.
+        let decoded = decode(0x080 | FMT1_PARAMETER_FLAG | 0x004 | (2 << FMT1_NUMBER_SHIFT));
+        assert_eq!(decoded.name, "TPM_RC_VALUE");
+        assert_eq!(decoded.subject, Some(TpmRcSubject::Parameter(2)));
+    }
+
+    #[test]
+    fn test_decode_unknown_code_falls_back() {
+        let decoded = decode(0x1ff);
+        assert_eq!(decoded.name, "TPM_RC_UNKNOWN");
+    }
+}
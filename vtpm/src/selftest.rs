@@ -0,0 +1,171 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! `TPM2_SelfTest` helper for running the backend's self test deterministically
+//! at a known point (e.g. TIS device construction) instead of only whenever
+//! the guest's own driver happens to issue it.
+//!
+//! Like [`crate::capability`], this builds and parses an actual TPM2 command
+//! sent through [`crate::TpmBackend::deliver_request`] rather than speaking
+//! the swtpm control channel.
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_CC_SELF_TEST: u32 = 0x0000_0143;
+const TPM_RC_SUCCESS: u32 = 0;
+
+/// Builds the fixed-size `TPM2_SelfTest(fullTest: YES)` command. The command
+/// has no variable-length fields, so its encoding is always exactly this
+/// many bytes.
+fn self_test_command() -> Vec<u8> {
+    let mut cmd = Vec::with_capacity(11);
+    cmd.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+    cmd.extend_from_slice(&11u32.to_be_bytes()); // commandSize
+    cmd.extend_from_slice(&TPM_CC_SELF_TEST.to_be_bytes());
+    cmd.push(1); // fullTest: YES
+    cmd
+}
+
+/// Issues `TPM2_SelfTest(fullTest: YES)` through `backend` and returns once
+/// the backend reports it completed. Intended to be called once, e.g. at TIS
+/// device construction time, so boot can be made to depend on a deterministic
+/// self test instead of whatever the guest's own driver happens to run.
+pub fn run_self_test(backend: &mut dyn TpmBackend) -> Result<()> {
+    let response = backend.deliver_request(&self_test_command())?;
+
+    // Header: tag (2) + responseSize (4) + responseCode (4).
+    let response_code = u32::from_be_bytes(
+        response
+            .get(6..10)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    );
+    if response_code != TPM_RC_SUCCESS {
+        return Err(Error::TpmCommandFailed(response_code));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(response_code: u32) -> Vec<u8> {
+        let mut response = Vec::new();
+        response.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        response.extend_from_slice(&10u32.to_be_bytes()); // responseSize, unused by the parser
+        response.extend_from_slice(&response_code.to_be_bytes());
+        response
+    }
+
+    struct StubBackend {
+        response: Vec<u8>,
+    }
+
+    impl TpmBackend for StubBackend {
+        fn startup(&mut self, _init: crate::ptm::PtmInit) -> Result<()> {
+            Ok(())
+        }
+
+        fn store_volatile(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn deliver_request(&mut self, _cmd: &[u8]) -> Result<Vec<u8>> {
+            Ok(self.response.clone())
+        }
+
+        fn cancel_cmd(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_established_flag(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_buffer_size(&mut self, requested: u32) -> Result<crate::ptm::PtmSetBufferSize> {
+            Ok(crate::ptm::PtmSetBufferSize {
+                buffersize: requested,
+                minsize: requested,
+                maxsize: requested,
+            })
+        }
+
+        fn hash_start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_end(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_config(&mut self) -> Result<crate::ptm::PtmGetConfig> {
+            Ok(crate::ptm::PtmGetConfig { flags: 0 })
+        }
+
+        fn get_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _passphrase: Option<&[u8]>,
+        ) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn set_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _data: &[u8],
+            _passphrase: Option<&[u8]>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_self_test_succeeds_on_tpm_rc_success() {
+        let mut backend = StubBackend {
+            response: response(TPM_RC_SUCCESS),
+        };
+        assert!(run_self_test(&mut backend).is_ok());
+    }
+
+    #[test]
+    fn test_run_self_test_reports_tpm_error_response_code() {
+        let mut backend = StubBackend {
+            response: response(0x0000_0101),
+        };
+        let err = run_self_test(&mut backend).unwrap_err();
+        assert!(matches!(err, Error::TpmCommandFailed(0x0000_0101)));
+    }
+
+    #[test]
+    fn test_run_self_test_rejects_truncated_response() {
+        let mut backend = StubBackend {
+            response: vec![0u8; 5],
+        };
+        assert!(matches!(
+            run_self_test(&mut backend).unwrap_err(),
+            Error::MalformedResponse
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! `TPM2_Shutdown`/`TPM2_Startup` helpers, for a caller that wants to drive
+//! the guest-visible reboot handshake itself instead of leaving it to
+//! firmware.
+//!
+//! Like [`crate::capability`], [`crate::selftest`] and [`crate::random`],
+//! these build and parse actual TPM2 commands sent through
+//! [`crate::TpmBackend::deliver_request`] rather than speaking the swtpm
+//! control channel. They are independent of
+//! [`crate::TpmBackend::startup`] (the swtpm `CmdInit` handshake, which
+//! brings the backend process itself up and is always required before any
+//! TPM2 command can be sent) and of
+//! [`crate::ptm::PTM_INIT_FLAG_DELETE_VOLATILE`] (whether `CmdInit` discards
+//! a prior `CmdStoreVolatile` blob): `TPM2_Shutdown(STATE)` is what actually
+//! tells the TPM to persist the state that makes PCR values and other
+//! volatile data survive a following `TPM2_Startup(STATE)`.
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_CC_SHUTDOWN: u32 = 0x0000_0145;
+const TPM_CC_STARTUP: u32 = 0x0000_0144;
+const TPM_RC_SUCCESS: u32 = 0;
+
+/// `TPM_SU` values distinguishing an orderly shutdown/startup that is
+/// expected to preserve PCR values and other volatile state
+/// ([`ShutdownType::State`]) from one that resets them to their
+/// power-on-of-shelf values ([`ShutdownType::Clear`]), the same choice a
+/// real platform's firmware makes between an S3 resume and a full reboot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownType {
+    Clear,
+    State,
+}
+
+impl ShutdownType {
+    fn tpm_su(self) -> u16 {
+        match self {
+            ShutdownType::Clear => 0x0000,
+            ShutdownType::State => 0x0001,
+        }
+    }
+}
+
+/// Builds the fixed-size `TPM2_Shutdown(shutdownType)`/`TPM2_Startup(startupType)`
+/// command; both have the identical shape of a single `TPM_SU` parameter, so
+/// one builder serves both.
+fn su_command(cc: u32, su: ShutdownType) -> Vec<u8> {
+    let mut cmd = Vec::with_capacity(12);
+    cmd.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+    cmd.extend_from_slice(&12u32.to_be_bytes()); // commandSize
+    cmd.extend_from_slice(&cc.to_be_bytes());
+    cmd.extend_from_slice(&su.tpm_su().to_be_bytes());
+    cmd
+}
+
+fn response_code(response: &[u8]) -> Result<u32> {
+    // Header: tag (2) + responseSize (4) + responseCode (4).
+    Ok(u32::from_be_bytes(
+        response
+            .get(6..10)
+            .ok_or(Error::MalformedResponse)?
+            .try_into()
+            .unwrap(),
+    ))
+}
+
+/// Issues `TPM2_Shutdown(shutdown_type)` through `backend`, e.g. right
+/// before [`crate::TpmBackend::startup`] replays `CmdInit` on a guest
+/// reboot, so the backend gets the same orderly-shutdown notice firmware
+/// would otherwise be trusted to send on its own.
+pub fn send_shutdown(backend: &mut dyn TpmBackend, shutdown_type: ShutdownType) -> Result<()> {
+    let response = backend.deliver_request(&su_command(TPM_CC_SHUTDOWN, shutdown_type))?;
+    match response_code(&response)? {
+        TPM_RC_SUCCESS => Ok(()),
+        code => Err(Error::TpmCommandFailed(code)),
+    }
+}
+
+/// Issues `TPM2_Startup(startup_type)` through `backend`, picking up where
+/// [`crate::TpmBackend::startup`]'s `CmdInit` leaves off: `CmdInit` only
+/// brings the backend process itself up, and the command interface refuses
+/// every other command with `TPM_RC_INITIALIZE` until `TPM2_Startup` runs.
+pub fn send_startup(backend: &mut dyn TpmBackend, startup_type: ShutdownType) -> Result<()> {
+    let response = backend.deliver_request(&su_command(TPM_CC_STARTUP, startup_type))?;
+    match response_code(&response)? {
+        TPM_RC_SUCCESS => Ok(()),
+        code => Err(Error::TpmCommandFailed(code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(response_code: u32) -> Vec<u8> {
+        let mut response = Vec::new();
+        response.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        response.extend_from_slice(&10u32.to_be_bytes()); // responseSize, unused by the parser
+        response.extend_from_slice(&response_code.to_be_bytes());
+        response
+    }
+
+    struct StubBackend {
+        response: Vec<u8>,
+        last_command: Vec<u8>,
+    }
+
+    impl TpmBackend for StubBackend {
+        fn startup(&mut self, _init: crate::ptm::PtmInit) -> Result<()> {
+            Ok(())
+        }
+
+        fn store_volatile(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+            self.last_command = cmd.to_vec();
+            Ok(self.response.clone())
+        }
+
+        fn cancel_cmd(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_established_flag(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_locality(&mut self, _locality: u8) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_buffer_size(&mut self, requested: u32) -> Result<crate::ptm::PtmSetBufferSize> {
+            Ok(crate::ptm::PtmSetBufferSize {
+                buffersize: requested,
+                minsize: requested,
+                maxsize: requested,
+            })
+        }
+
+        fn hash_start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn hash_end(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_config(&mut self) -> Result<crate::ptm::PtmGetConfig> {
+            Ok(crate::ptm::PtmGetConfig { flags: 0 })
+        }
+
+        fn get_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _passphrase: Option<&[u8]>,
+        ) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn set_state_blob(
+            &mut self,
+            _blob_type: crate::ptm::StateBlobType,
+            _data: &[u8],
+            _passphrase: Option<&[u8]>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_send_shutdown_encodes_the_requested_shutdown_type() {
+        let mut backend = StubBackend {
+            response: response(TPM_RC_SUCCESS),
+            last_command: Vec::new(),
+        };
+        send_shutdown(&mut backend, ShutdownType::State).unwrap();
+        assert_eq!(
+            backend.last_command,
+            su_command(TPM_CC_SHUTDOWN, ShutdownType::State)
+        );
+    }
+
+    #[test]
+    fn test_send_startup_encodes_the_requested_startup_type() {
+        let mut backend = StubBackend {
+            response: response(TPM_RC_SUCCESS),
+            last_command: Vec::new(),
+        };
+        send_startup(&mut backend, ShutdownType::Clear).unwrap();
+        assert_eq!(
+            backend.last_command,
+            su_command(TPM_CC_STARTUP, ShutdownType::Clear)
+        );
+    }
+
+    #[test]
+    fn test_send_shutdown_propagates_a_non_success_response_code() {
+        let mut backend = StubBackend {
+            response: response(0x0922),
+            last_command: Vec::new(),
+        };
+        let err = send_shutdown(&mut backend, ShutdownType::Clear).unwrap_err();
+        assert!(matches!(err, Error::TpmCommandFailed(0x0922)));
+    }
+}
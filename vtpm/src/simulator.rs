@@ -0,0 +1,138 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::Path;
+
+use tpm2::Simulator;
+
+use crate::backend::TpmBackend;
+use crate::error::{Error, Result};
+use crate::ptm::{cap_bits, Capabilities, PtmGetConfig, PtmInit, PtmSetBufferSize, StateBlobType};
+
+/// In-process TPM 2.0 implementation, for deployments that would rather not
+/// manage an external `swtpm` process. State is persisted as a set of files
+/// under `state_dir`.
+pub struct TpmSimulator {
+    inner: Simulator,
+}
+
+impl TpmSimulator {
+    pub fn new<P: AsRef<Path>>(state_dir: P) -> Result<Self> {
+        let inner = Simulator::new(state_dir.as_ref())
+            .map_err(|e| Error::Simulator(e.to_string()))?;
+        Ok(TpmSimulator { inner })
+    }
+}
+
+impl TpmBackend for TpmSimulator {
+    fn startup(&mut self, _init: PtmInit) -> Result<()> {
+        // The simulator's `power_on` has no equivalent of the swtpm wire
+        // protocol's delete-volatile flag; it always starts from whatever
+        // state is on disk in `state_dir`.
+        self.inner
+            .power_on()
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn store_volatile(&mut self) -> Result<()> {
+        // The simulator persists state directly to `state_dir` as it runs;
+        // there is no separate volatile state to flush out.
+        Ok(())
+    }
+
+    fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+        self.inner
+            .execute_command(cmd)
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn cancel_cmd(&mut self) -> Result<()> {
+        // The in-process simulator executes commands synchronously, so
+        // there is never an in-flight command to cancel.
+        Ok(())
+    }
+
+    fn get_established_flag(&mut self) -> Result<bool> {
+        Ok(self.inner.established())
+    }
+
+    fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+        self.inner
+            .reset_established()
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn set_locality(&mut self, locality: u8) -> Result<()> {
+        self.inner
+            .set_locality(locality)
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize> {
+        // The in-process simulator has no wire buffer to negotiate; it just
+        // accepts whatever size it is asked for.
+        Ok(PtmSetBufferSize {
+            buffersize: requested,
+            minsize: requested,
+            maxsize: requested,
+        })
+    }
+
+    fn hash_start(&mut self) -> Result<()> {
+        self.inner
+            .hash_start()
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn hash_data(&mut self, data: &[u8]) -> Result<()> {
+        self.inner
+            .hash_data(data)
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn hash_end(&mut self) -> Result<()> {
+        self.inner
+            .hash_end()
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.inner
+            .power_off()
+            .map_err(|e| Error::Simulator(e.to_string()))
+    }
+
+    fn get_config(&mut self) -> Result<PtmGetConfig> {
+        // The in-process simulator has no notion of encrypting its state
+        // directory; it never reports any config flags as set.
+        Ok(PtmGetConfig { flags: 0 })
+    }
+
+    fn get_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _passphrase: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        // The in-process simulator persists its state directly to
+        // `state_dir` as it runs; it has no separate state-blob export
+        // mechanism to hook a VM snapshot into.
+        Err(Error::Unsupported)
+    }
+
+    fn set_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _data: &[u8],
+        _passphrase: Option<&[u8]>,
+    ) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        // Mirrors get_state_blob/set_state_blob above: everything else this
+        // trait asks of a backend, the simulator does; only the state-blob
+        // export/import path has no equivalent.
+        Ok(Capabilities::all().without(cap_bits::GET_STATEBLOB | cap_bits::SET_STATEBLOB))
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Ownership and permission hardening for [`TpmSimulator`](crate::TpmSimulator)'s
+//! `state_dir`.
+//!
+//! The simulator persists NVRAM, PCR banks and other TPM state as plain
+//! files under `state_dir`, created with whatever umask the VMM process
+//! happens to be running under. A host that drops privileges after setup
+//! (or that simply wants the directory owned by a dedicated, unprivileged
+//! user rather than the VMM's own) needs a way to re-assert ownership and
+//! mode on it once the simulator has finished laying out its files.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+/// Applies `uid`/`gid`/`mode` to `state_dir`, leaving any field that is
+/// `None` untouched. A no-op if all three are `None`.
+///
+/// This only touches the directory itself, not the files the simulator has
+/// already created inside it: those are read and written exclusively by
+/// this process, so their ownership doesn't need to change for a dropped-
+/// privilege process to keep working, and `swtpm`-managed sockets (the
+/// `Emulator`/`EmulatorTcp` backends) are untouched by this function
+/// entirely, since this process only connects to those, it never creates
+/// them.
+pub fn secure_state_dir(
+    state_dir: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    mode: Option<u32>,
+) -> Result<()> {
+    if uid.is_some() || gid.is_some() {
+        chown(state_dir, uid, gid)?;
+    }
+    if let Some(mode) = mode {
+        fs::set_permissions(state_dir, fs::Permissions::from_mode(mode))
+            .map_err(Error::StateDirPermissions)?;
+    }
+    Ok(())
+}
+
+fn chown(path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+    let c_path = std::ffi::CString::new(path.as_os_str().to_string_lossy().as_bytes())
+        .map_err(|_| {
+            Error::StateDirPermissions(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "state_dir path contains a nul byte",
+            ))
+        })?;
+    // SAFETY: `c_path` is a valid, nul-terminated C string for the duration
+    // of the call; `libc::chown` only reads it. Passing `-1` (cast to the
+    // platform's uid_t/gid_t) for whichever of `uid`/`gid` is `None` leaves
+    // that part of the ownership unchanged, per `chown(2)`.
+    let ret = unsafe {
+        libc::chown(
+            c_path.as_ptr(),
+            uid.unwrap_or(u32::MAX),
+            gid.unwrap_or(u32::MAX),
+        )
+    };
+    if ret != 0 {
+        return Err(Error::StateDirPermissions(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secure_state_dir_applies_mode() {
+        let dir = std::env::temp_dir().join(format!(
+            "ch-tpm-state-dir-test-{}-{}",
+            std::process::id(),
+            "mode"
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        secure_state_dir(&dir, None, None, Some(0o700)).unwrap();
+
+        let mode = fs::metadata(&dir).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o700);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_secure_state_dir_is_a_no_op_with_no_fields_set() {
+        let dir = std::env::temp_dir().join(format!(
+            "ch-tpm-state-dir-test-{}-{}",
+            std::process::id(),
+            "noop"
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        secure_state_dir(&dir, None, None, None).unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
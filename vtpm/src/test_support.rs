@@ -0,0 +1,251 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Test doubles shared across crates, gated behind the `test-utils` feature
+//! so `devices` (and any other consumer of [`crate::TpmBackend`]) can pull
+//! them in as a dev-dependency without dragging them into non-test builds.
+//! [`MockSwtpm`] is an in-process stand-in for `swtpm`'s control+data
+//! channel protocol, for exercising [`crate::TpmEmulator`] (and, via it, the
+//! `devices` crate's `TpmTisCore`/`TPMIsa`) in CI without spawning a real
+//! `swtpm` binary. [`MockBackend`] is a plain in-process [`crate::TpmBackend`]
+//! implementation, for a test that just needs something implementing the
+//! trait without exercising any particular backend's wire protocol.
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixListener;
+use std::thread;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::ptm::{cap_bits, Commands, PtmGetConfig, PtmInit, PtmSetBufferSize, StateBlobType, TPM_CONFIG_FLAG_STATE_ENCRYPTION};
+use crate::{Result, TpmBackend};
+
+/// Minimal control/data channel server that speaks just enough of the PTM
+/// protocol to exercise a real [`crate::TpmEmulator`] dialed against it,
+/// rather than a hand-rolled [`crate::TpmBackend`] test double: commands are
+/// answered with the same canned, always-succeeding responses a freshly
+/// started `swtpm` would give for `TPM2_Startup`, and one TPM command on the
+/// data channel is echoed back as a well-formed, empty-body success
+/// response.
+pub struct MockSwtpm {
+    pub ctrl_path: std::path::PathBuf,
+    pub data_path: std::path::PathBuf,
+}
+
+impl MockSwtpm {
+    pub fn new(name: &str) -> Self {
+        let ctrl_path =
+            std::path::PathBuf::from(format!("/tmp/vtpm_test_{}_{}.ctrl", name, std::process::id()));
+        let data_path =
+            std::path::PathBuf::from(format!("/tmp/vtpm_test_{}_{}.data", name, std::process::id()));
+        let _ = std::fs::remove_file(&ctrl_path);
+        let _ = std::fs::remove_file(&data_path);
+
+        let ctrl_listener = UnixListener::bind(&ctrl_path).unwrap();
+        let data_listener = UnixListener::bind(&data_path).unwrap();
+
+        thread::spawn(move || {
+            let (mut ctrl, _) = ctrl_listener.accept().unwrap();
+            let (mut data, _) = data_listener.accept().unwrap();
+
+            // TPM commands on the data channel are served on their own thread,
+            // each answered with a header-only success response, so the
+            // control loop below never waits on the data channel.
+            thread::spawn(move || {
+                let mut tag_len = [0u8; 10];
+                while data.read_exact(&mut tag_len).is_ok() {
+                    data.write_all(&[0x80, 0x01, 0, 0, 0, 10, 0, 0, 0, 0]).unwrap();
+                }
+            });
+
+            loop {
+                let mut header = [0u8; 4];
+                if ctrl.read_exact(&mut header).is_err() {
+                    break;
+                }
+                let cmd = BigEndian::read_u32(&header);
+
+                if cmd == Commands::CmdSetLocality as u32
+                    || cmd == Commands::CmdResetTpmEstablished as u32
+                {
+                    let mut payload = [0u8; 1];
+                    ctrl.read_exact(&mut payload).unwrap();
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                } else if cmd == Commands::CmdGetTpmEstablished as u32 {
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                    ctrl.write_all(&[0u8]).unwrap();
+                } else if cmd == Commands::CmdGetConfig as u32 {
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                    ctrl.write_all(&TPM_CONFIG_FLAG_STATE_ENCRYPTION.to_be_bytes())
+                        .unwrap();
+                } else if cmd == Commands::CmdGetStateBlob as u32 {
+                    let mut header = [0u8; 12];
+                    ctrl.read_exact(&mut header).unwrap();
+                    let passphrase_len = BigEndian::read_u32(&header[8..12]) as usize;
+                    let mut passphrase = vec![0u8; passphrase_len];
+                    ctrl.read_exact(&mut passphrase).unwrap();
+                    // `offset` always trails the header/passphrase per
+                    // `PtmGetState::encode`; the whole blob always fits in
+                    // one chunk here, so it's only ever zero, but it still
+                    // has to be read off the wire to keep framing in sync.
+                    let mut offset = [0u8; 4];
+                    ctrl.read_exact(&mut offset).unwrap();
+
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                    let blob = b"fake-state-blob";
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap(); // state_flags: no more chunks
+                    ctrl.write_all(&(blob.len() as u32).to_be_bytes()).unwrap(); // totlength
+                    ctrl.write_all(&(blob.len() as u32).to_be_bytes()).unwrap(); // length
+                    ctrl.write_all(blob).unwrap();
+                } else if cmd == Commands::CmdSetStateBlob as u32 {
+                    let mut header = [0u8; 12];
+                    ctrl.read_exact(&mut header).unwrap();
+                    let passphrase_len = BigEndian::read_u32(&header[8..12]) as usize;
+                    let mut passphrase = vec![0u8; passphrase_len];
+                    ctrl.read_exact(&mut passphrase).unwrap();
+
+                    let mut len_buf = [0u8; 4];
+                    ctrl.read_exact(&mut len_buf).unwrap();
+                    let mut blob = vec![0u8; BigEndian::read_u32(&len_buf) as usize];
+                    ctrl.read_exact(&mut blob).unwrap();
+
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                } else if cmd == Commands::CmdGetCapability as u32 {
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                    ctrl.write_all(
+                        &(cap_bits::CANCEL_TPM_CMD | cap_bits::GET_STATEBLOB | cap_bits::SET_STATEBLOB)
+                            .to_be_bytes(),
+                    )
+                    .unwrap();
+                } else if cmd == Commands::CmdSetBufferSize as u32 {
+                    let mut payload = [0u8; 4];
+                    ctrl.read_exact(&mut payload).unwrap();
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                    ctrl.write_all(&4096u32.to_be_bytes()).unwrap();
+                    ctrl.write_all(&128u32.to_be_bytes()).unwrap();
+                    ctrl.write_all(&4096u32.to_be_bytes()).unwrap();
+                } else if cmd == Commands::CmdCancelTpmCmd as u32
+                    || cmd == Commands::CmdInit as u32
+                    || cmd == Commands::CmdShutdown as u32
+                    || cmd == Commands::CmdStoreVolatile as u32
+                    || cmd == Commands::CmdStop as u32
+                {
+                    if cmd == Commands::CmdInit as u32 {
+                        let mut payload = [0u8; 4];
+                        ctrl.read_exact(&mut payload).unwrap();
+                    }
+                    ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                }
+            }
+        });
+
+        MockSwtpm {
+            ctrl_path,
+            data_path,
+        }
+    }
+}
+
+impl Drop for MockSwtpm {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.ctrl_path);
+        let _ = std::fs::remove_file(&self.data_path);
+    }
+}
+
+/// An in-process [`TpmBackend`] double that never touches a socket, for
+/// crates that only need something implementing the trait (e.g. to drive
+/// `devices::legacy::tpm_tis::TPMIsa` end to end) rather than exercising a
+/// specific backend's wire protocol the way [`MockSwtpm`] does for
+/// [`crate::TpmEmulator`]. Every TPM command is echoed straight back as its
+/// own response, the same convention the crate's own private test doubles
+/// (e.g. `devices::legacy::tpm_tis_core`'s `FakeBackend`) already use.
+#[derive(Default)]
+pub struct MockBackend {
+    pub established_flag: bool,
+    pub buffer_size: u32,
+    pub startup_calls: u32,
+    pub cancel_calls: u32,
+}
+
+impl TpmBackend for MockBackend {
+    fn startup(&mut self, _init: PtmInit) -> Result<()> {
+        self.startup_calls += 1;
+        Ok(())
+    }
+
+    fn store_volatile(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn deliver_request(&mut self, cmd: &[u8]) -> Result<Vec<u8>> {
+        Ok(cmd.to_vec())
+    }
+
+    fn cancel_cmd(&mut self) -> Result<()> {
+        self.cancel_calls += 1;
+        Ok(())
+    }
+
+    fn get_established_flag(&mut self) -> Result<bool> {
+        Ok(self.established_flag)
+    }
+
+    fn reset_established_flag(&mut self, _locality: u8) -> Result<()> {
+        self.established_flag = false;
+        Ok(())
+    }
+
+    fn set_locality(&mut self, _locality: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, requested: u32) -> Result<PtmSetBufferSize> {
+        if requested != 0 {
+            self.buffer_size = requested;
+        }
+        Ok(PtmSetBufferSize {
+            buffersize: self.buffer_size,
+            minsize: self.buffer_size,
+            maxsize: self.buffer_size,
+        })
+    }
+
+    fn hash_start(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn hash_data(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn hash_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_config(&mut self) -> Result<PtmGetConfig> {
+        Ok(PtmGetConfig { flags: 0 })
+    }
+
+    fn get_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _passphrase: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn set_state_blob(
+        &mut self,
+        _blob_type: StateBlobType,
+        _data: &[u8],
+        _passphrase: Option<&[u8]>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}